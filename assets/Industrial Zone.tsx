@@ -185,6 +185,9 @@
   <image source="tileset/1 Tiles/IndustrialTile_61.png" width="32" height="32"/>
  </tile>
  <tile id="61">
+  <properties>
+   <property name="grapple" type="bool" value="true"/>
+  </properties>
   <image source="tileset/1 Tiles/IndustrialTile_62.png" width="32" height="32"/>
  </tile>
  <tile id="62">
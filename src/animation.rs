@@ -0,0 +1,272 @@
+//! Sprite sheet animation for the players, picked from what Tnua and the chain are doing.
+//!
+//! Every character has an `.anim.ron` file in `assets/animations` listing its sheet and which
+//! frames of it make up each [`AnimationState`]. Until that is loaded the player shows the plain
+//! character image.
+
+use std::collections::HashMap;
+
+use avian2d::prelude::*;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use bevy_tnua::prelude::*;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::impact::Slamming;
+use crate::round::Health;
+use crate::{Chain, ChainBase, Player};
+
+/// Moving slower than this is standing still, and doesn't turn the player around.
+const RUN_SPEED: f32 = 60.0;
+/// How long the player flinches after losing health, in seconds.
+const HURT_TIME: f32 = 0.3;
+/// How long a windup or strike is shown at least, so a quick flick of the chain still reads as
+/// one.
+const CHAIN_POSE_TIME: f32 = 0.2;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum AnimationState {
+    #[default]
+    Idle,
+    Run,
+    Jump,
+    Fall,
+    Slam,
+    Hurt,
+    /// Pulling the chain back, away from the middle.
+    Windup,
+    /// Swinging the chain through, back toward the middle and past it.
+    Strike,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnimationClip {
+    /// Indices into the sheet.
+    pub frames: Vec<usize>,
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+}
+
+fn default_fps() -> f32 {
+    8.0
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+/// The layout of a sprite sheet, as written in the `.ron` file.
+#[derive(Deserialize)]
+struct AnimationSetDef {
+    sheet: String,
+    frame_size: (u32, u32),
+    columns: u32,
+    rows: u32,
+    animations: HashMap<AnimationState, AnimationClip>,
+}
+
+/// A character's sprite sheet and every animation in it.
+#[derive(Asset, TypePath)]
+pub struct AnimationSet {
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+    pub animations: HashMap<AnimationState, AnimationClip>,
+}
+
+#[derive(Default)]
+struct AnimationSetLoader;
+
+#[derive(Debug, Error)]
+pub enum AnimationSetError {
+    #[error("Could not read animations: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed animations: {0}")]
+    Ron(#[from] ron::de::SpannedError),
+}
+
+impl AssetLoader for AnimationSetLoader {
+    type Asset = AnimationSet;
+    type Settings = ();
+    type Error = AnimationSetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let def: AnimationSetDef = ron::de::from_bytes(&bytes)?;
+
+        let layout = TextureAtlasLayout::from_grid(
+            UVec2::new(def.frame_size.0, def.frame_size.1),
+            def.columns,
+            def.rows,
+            None,
+            None,
+        );
+        Ok(AnimationSet {
+            image: load_context.load(def.sheet),
+            layout: load_context.add_labeled_asset("layout".to_string(), layout),
+            animations: def.animations,
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+/// Animates the sprite of a player.
+#[derive(Component)]
+pub struct CharacterAnimation {
+    set: Handle<AnimationSet>,
+    state: AnimationState,
+    /// Seconds since `state` started.
+    elapsed: f32,
+    /// Seconds the current hurt, windup or strike is still shown for.
+    hold: f32,
+    last_health: Option<f32>,
+    last_chain: f32,
+}
+
+impl CharacterAnimation {
+    pub fn new(set: Handle<AnimationSet>) -> Self {
+        Self {
+            set,
+            state: AnimationState::Idle,
+            elapsed: 0.0,
+            hold: 0.0,
+            last_health: None,
+            last_chain: 0.0,
+        }
+    }
+}
+
+pub struct AnimationPlugin;
+
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AnimationSet>()
+            .register_asset_loader(AnimationSetLoader)
+            .add_systems(Update, (pick_animation, play_animation).chain());
+    }
+}
+
+fn pick_animation(
+    time: Res<Time>,
+    players: Query<
+        (
+            &mut CharacterAnimation,
+            &mut Sprite,
+            &TnuaController,
+            &LinearVelocity,
+            &Chain,
+            Option<&Health>,
+            Has<Slamming>,
+        ),
+        With<Player>,
+    >,
+    bases: Query<&ChainBase>,
+) {
+    for (mut animation, mut sprite, controller, velocity, chain, health, slamming) in players {
+        animation.hold = (animation.hold - time.delta_secs()).max(0.0);
+
+        let hurt = health.is_some_and(|health| {
+            animation
+                .last_health
+                .is_some_and(|last| health.current < last)
+        });
+        animation.last_health = health.map(|health| health.current);
+
+        // Where the chain is held, -1 all the way left to 1 all the way right.
+        let chain_pos = bases
+            .get(chain.base)
+            .map(|base| base.getPos().x / 35.0)
+            .unwrap_or_default();
+        let chain_moved = chain_pos - animation.last_chain;
+        animation.last_chain = chain_pos;
+
+        let state = if hurt {
+            animation.hold = HURT_TIME;
+            AnimationState::Hurt
+        } else if animation.state == AnimationState::Hurt && animation.hold > 0.0 {
+            AnimationState::Hurt
+        } else if chain_moved != 0.0 {
+            animation.hold = CHAIN_POSE_TIME;
+            if chain_moved.signum() != chain_pos.signum() {
+                AnimationState::Strike
+            } else {
+                AnimationState::Windup
+            }
+        } else if animation.hold > 0.0 {
+            animation.state
+        } else if slamming {
+            AnimationState::Slam
+        } else if controller.is_airborne().unwrap_or(false) {
+            if velocity.y > 0.0 {
+                AnimationState::Jump
+            } else {
+                AnimationState::Fall
+            }
+        } else if velocity.x.abs() > RUN_SPEED {
+            AnimationState::Run
+        } else {
+            AnimationState::Idle
+        };
+
+        if state != animation.state || hurt {
+            animation.state = state;
+            animation.elapsed = 0.0;
+        }
+
+        if velocity.x.abs() > RUN_SPEED {
+            sprite.flip_x = velocity.x < 0.0;
+        }
+    }
+}
+
+fn play_animation(
+    time: Res<Time>,
+    sets: Res<Assets<AnimationSet>>,
+    players: Query<(&mut CharacterAnimation, &mut Sprite)>,
+) {
+    for (mut animation, mut sprite) in players {
+        let Some(set) = sets.get(&animation.set) else {
+            continue;
+        };
+        animation.elapsed += time.delta_secs();
+
+        // Swap the plain image for the sheet once it is loaded.
+        if sprite.texture_atlas.is_none() {
+            sprite.image = set.image.clone();
+            sprite.texture_atlas = Some(TextureAtlas::from(set.layout.clone()));
+        }
+
+        let Some(clip) = set
+            .animations
+            .get(&animation.state)
+            .or_else(|| set.animations.get(&AnimationState::Idle))
+        else {
+            continue;
+        };
+        if clip.frames.is_empty() {
+            continue;
+        }
+
+        let frame = (animation.elapsed * clip.fps) as usize;
+        let frame = if clip.looping {
+            frame % clip.frames.len()
+        } else {
+            frame.min(clip.frames.len() - 1)
+        };
+        if let Some(atlas) = &mut sprite.texture_atlas {
+            atlas.index = clip.frames[frame];
+        }
+    }
+}
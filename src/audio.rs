@@ -0,0 +1,264 @@
+//! Music and sound effects.
+//!
+//! Gameplay code doesn't play sounds itself, it sends a [`SfxEvent`] and this module picks it up,
+//! positions it in the world relative to the camera and applies the volume buses.
+
+use avian2d::prelude::*;
+use bevy::audio::{DefaultSpatialScale, SpatialScale, Volume};
+use bevy::prelude::*;
+use bevy_tnua::TnuaAction;
+use bevy_tnua::prelude::*;
+
+use crate::camera::{CameraRig, CameraTarget};
+use crate::impact::{ImpactEvent, ImpactKind};
+use crate::{Chain, Player};
+
+pub mod music;
+
+/// One unit of distance in the audio engine is this many pixels. Sounds further than that from
+/// the camera start to fade out.
+const PIXELS_PER_AUDIO_UNIT: f32 = 600.0;
+/// Same speed `woosh_chain` leaves a trail at.
+const WHIP_CRACK_SPEED: f32 = 1000.0;
+/// An impact this strong plays at full volume.
+const LOUDEST_IMPACT: f32 = 3000.0;
+/// Walking slower than this is silent.
+const FOOTSTEP_MIN_SPEED: f32 = 100.0;
+const FOOTSTEP_INTERVAL: f32 = 0.28;
+
+/// Volume buses, all linear from 0 to 1. The effective volume of a sound is `master` times the
+/// volume of its bus.
+#[derive(Resource)]
+pub struct AudioVolumes {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for AudioVolumes {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 0.6,
+            sfx: 0.8,
+        }
+    }
+}
+
+impl AudioVolumes {
+    pub fn music(&self) -> Volume {
+        Volume::Linear(self.master * self.music)
+    }
+
+    pub fn sfx(&self) -> Volume {
+        Volume::Linear(self.master * self.sfx)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sfx {
+    WhipCrack,
+    Hit,
+    Jump,
+    Slam,
+    Land,
+    Footstep,
+}
+
+/// Play `sound` at `position` in the world.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SfxEvent {
+    pub sound: Sfx,
+    pub position: Vec2,
+    /// Linear, on top of the sfx bus.
+    pub volume: f32,
+}
+
+#[derive(Resource)]
+struct SfxHandles {
+    whip_crack: Handle<AudioSource>,
+    hit: Handle<AudioSource>,
+    jump: Handle<AudioSource>,
+    slam: Handle<AudioSource>,
+    land: Handle<AudioSource>,
+    footstep: Handle<AudioSource>,
+}
+
+impl FromWorld for SfxHandles {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Self {
+            whip_crack: asset_server.load("sfx/whip_crack.wav"),
+            hit: asset_server.load("sfx/hit.wav"),
+            jump: asset_server.load("sfx/jump.wav"),
+            slam: asset_server.load("sfx/slam.wav"),
+            land: asset_server.load("sfx/land.wav"),
+            footstep: asset_server.load("sfx/footstep.wav"),
+        }
+    }
+}
+
+impl SfxHandles {
+    fn get(&self, sound: Sfx) -> Handle<AudioSource> {
+        match sound {
+            Sfx::WhipCrack => self.whip_crack.clone(),
+            Sfx::Hit => self.hit.clone(),
+            Sfx::Jump => self.jump.clone(),
+            Sfx::Slam => self.slam.clone(),
+            Sfx::Land => self.land.clone(),
+            Sfx::Footstep => self.footstep.clone(),
+        }
+    }
+}
+
+/// What the player was doing last frame, to tell when a sound should start.
+#[derive(Component, Default)]
+struct SfxState {
+    airborne: bool,
+    action: Option<&'static str>,
+    whip_fast: bool,
+    until_footstep: f32,
+}
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AudioVolumes>()
+            .init_resource::<SfxHandles>()
+            .insert_resource(DefaultSpatialScale(SpatialScale::new_2d(
+                1.0 / PIXELS_PER_AUDIO_UNIT,
+            )))
+            .add_event::<SfxEvent>()
+            .add_plugins(music::MusicPlugin)
+            .add_systems(
+                Update,
+                (
+                    attach_listener,
+                    (
+                        track_new_players,
+                        movement_sounds,
+                        whip_cracks,
+                        impact_sounds,
+                        play_sfx,
+                    )
+                        .chain(),
+                ),
+            );
+    }
+}
+
+/// Sounds are heard from the shared camera, it follows everyone even while the screen is split.
+fn attach_listener(mut commands: Commands, cameras: Query<(Entity, &CameraRig), Added<CameraRig>>) {
+    for (camera, rig) in cameras {
+        if rig.target == CameraTarget::AllPlayers {
+            commands
+                .entity(camera)
+                .insert(SpatialListener::new(PIXELS_PER_AUDIO_UNIT));
+        }
+    }
+}
+
+fn track_new_players(mut commands: Commands, players: Query<Entity, Added<Player>>) {
+    for player in players {
+        commands.entity(player).insert(SfxState::default());
+    }
+}
+
+fn movement_sounds(
+    mut sfx: EventWriter<SfxEvent>,
+    time: Res<Time>,
+    players: Query<(&TnuaController, &LinearVelocity, &Transform, &mut SfxState)>,
+) {
+    for (controller, velocity, transform, mut state) in players {
+        let position = transform.translation.xy();
+        let airborne = controller.is_airborne().unwrap_or(false);
+        let action = controller.action_name();
+
+        if action != state.action && action == Some(TnuaBuiltinJump::NAME) {
+            sfx.write(SfxEvent {
+                sound: Sfx::Jump,
+                position,
+                volume: 1.0,
+            });
+        }
+        if state.airborne && !airborne {
+            sfx.write(SfxEvent {
+                sound: Sfx::Land,
+                position,
+                volume: 1.0,
+            });
+        }
+
+        state.until_footstep -= time.delta_secs();
+        if airborne || velocity.x.abs() < FOOTSTEP_MIN_SPEED {
+            state.until_footstep = 0.0;
+        } else if state.until_footstep <= 0.0 {
+            state.until_footstep = FOOTSTEP_INTERVAL;
+            sfx.write(SfxEvent {
+                sound: Sfx::Footstep,
+                position,
+                volume: 0.5,
+            });
+        }
+
+        state.airborne = airborne;
+        state.action = action;
+    }
+}
+
+/// Cracks once every time the tip of a whip goes past the speed of sound. Well, past
+/// [`WHIP_CRACK_SPEED`].
+fn whip_cracks(
+    mut sfx: EventWriter<SfxEvent>,
+    players: Query<(&Chain, &LinearVelocity, &mut SfxState), With<Player>>,
+    links: Query<(&LinearVelocity, &Transform)>,
+) {
+    for (chain, player_velocity, mut state) in players {
+        let Some((tip_velocity, tip_transform)) =
+            chain.links.last().and_then(|tip| links.get(*tip).ok())
+        else {
+            continue;
+        };
+
+        let fast = (tip_velocity.0 - player_velocity.0).length() > WHIP_CRACK_SPEED;
+        if fast && !state.whip_fast {
+            sfx.write(SfxEvent {
+                sound: Sfx::WhipCrack,
+                position: tip_transform.translation.xy(),
+                volume: 1.0,
+            });
+        }
+        state.whip_fast = fast;
+    }
+}
+
+fn impact_sounds(mut impacts: EventReader<ImpactEvent>, mut sfx: EventWriter<SfxEvent>) {
+    for impact in impacts.read().filter(|impact| !impact.resimulated) {
+        sfx.write(SfxEvent {
+            sound: match impact.kind {
+                ImpactKind::Whip | ImpactKind::Throw => Sfx::Hit,
+                ImpactKind::Slam => Sfx::Slam,
+            },
+            position: impact.position,
+            volume: (impact.strength / LOUDEST_IMPACT).clamp(0.2, 1.0),
+        });
+    }
+}
+
+fn play_sfx(
+    mut commands: Commands,
+    mut events: EventReader<SfxEvent>,
+    handles: Res<SfxHandles>,
+    volumes: Res<AudioVolumes>,
+) {
+    for event in events.read() {
+        commands.spawn((
+            AudioPlayer::new(handles.get(event.sound)),
+            PlaybackSettings::DESPAWN
+                .with_volume(volumes.sfx() * Volume::Linear(event.volume))
+                .with_spatial(true),
+            Transform::from_translation(event.position.extend(0.0)),
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+//! Music that follows the match: calm in the menus and the lobby, picking up during the countdown,
+//! full speed in the fight, faster still in sudden death and winding down once it is finished. It
+//! also speeds up and gets louder as someone gets close to being knocked out.
+//!
+//! Within a match the track keeps playing and glides from one phase to the next, so a new phase
+//! doesn't start it over. Every change of track is a cross-fade: the new track starts silent next to the old one, which
+//! fades out and is despawned once it can't be heard. All of them play through the one output
+//! stream bevy opens at startup, so on the web `restart-audio-context.js` only ever has a single
+//! `AudioContext` to unlock, however many layers come and go.
+
+use bevy::audio::Volume;
+use bevy::prelude::*;
+
+use super::AudioVolumes;
+use crate::GameState;
+use crate::round::{Health, MatchPhase};
+
+/// How long a cross-fade takes, in seconds.
+const FADE_TIME: f32 = 1.5;
+/// How much faster the music plays when someone is about to be knocked out.
+const INTENSITY_SPEEDUP: f32 = 0.12;
+/// How much louder the music gets when someone is about to be knocked out, relative to the cue.
+const INTENSITY_BOOST: f32 = 0.4;
+/// How quickly the intensity follows the health of the players, higher is snappier.
+const INTENSITY_SMOOTHING: f32 = 2.0;
+/// How quickly a playing track takes on the speed and volume of a new cue, higher is snappier.
+const CUE_SMOOTHING: f32 = 1.5;
+
+/// What should be playing, and how.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MusicCue {
+    pub track: &'static str,
+    /// Playback speed, which also shifts the pitch.
+    pub speed: f32,
+    /// Linear, on top of the music bus.
+    pub volume: f32,
+}
+
+impl MusicCue {
+    fn for_state(state: GameState, phase: Option<MatchPhase>) -> Self {
+        match (state, phase) {
+            (GameState::MainMenu | GameState::Lobby, _) => Self {
+                track: "ost.ogg",
+                speed: 0.85,
+                volume: 0.6,
+            },
+            (GameState::Playing, None | Some(MatchPhase::Countdown)) => Self {
+                track: "ost.ogg",
+                speed: 0.95,
+                volume: 0.75,
+            },
+            (GameState::Playing, Some(MatchPhase::Fight)) => Self {
+                track: "ost.ogg",
+                speed: 1.0,
+                volume: 0.9,
+            },
+            (GameState::Playing, Some(MatchPhase::SuddenDeath)) => Self {
+                track: "ost.ogg",
+                speed: 1.12,
+                volume: 1.0,
+            },
+            (GameState::Playing, Some(MatchPhase::Finished)) => Self {
+                track: "ost.ogg",
+                speed: 0.9,
+                volume: 0.7,
+            },
+        }
+    }
+}
+
+/// One playing track.
+#[derive(Component)]
+pub struct MusicLayer {
+    cue: MusicCue,
+    /// Speed and volume it is at, on its way to those of the cue.
+    speed: f32,
+    volume: f32,
+    /// Whether it was started for a match. Starting or leaving a match always starts the music
+    /// over, the menus and the lobby share theirs.
+    in_match: bool,
+    /// Cross-fade position, 0 is silent and 1 is the full volume of the cue.
+    gain: f32,
+    fading_out: bool,
+}
+
+/// 0 while everyone is healthy, up to 1 as the player worst off runs out of health.
+#[derive(Resource, Default)]
+pub struct MusicIntensity(pub f32);
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MusicIntensity>().add_systems(
+            Update,
+            (follow_state, update_intensity, fade_layers).chain(),
+        );
+    }
+}
+
+/// Starts the cue for the current state, fading out whatever else is playing. Within a match, or
+/// outside of one, the layer already playing the track just takes on the new cue.
+fn follow_state(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    state: Res<State<GameState>>,
+    phase: Option<Res<State<MatchPhase>>>,
+    layers: Query<&mut MusicLayer>,
+) {
+    let state = *state.get();
+    let cue = MusicCue::for_state(state, phase.map(|phase| *phase.get()));
+    let in_match = state == GameState::Playing;
+
+    let mut playing = false;
+    for mut layer in layers {
+        if layer.fading_out {
+            continue;
+        }
+        if layer.in_match == in_match && layer.cue.track == cue.track {
+            layer.cue = cue;
+            playing = true;
+        } else {
+            layer.fading_out = true;
+        }
+    }
+
+    if !playing {
+        commands.spawn((
+            MusicLayer {
+                cue,
+                speed: cue.speed,
+                volume: cue.volume,
+                in_match,
+                gain: 0.0,
+                fading_out: false,
+            },
+            AudioPlayer::new(asset_server.load(cue.track)),
+            PlaybackSettings::LOOP
+                .with_volume(Volume::Linear(0.0))
+                .with_speed(cue.speed),
+        ));
+    }
+}
+
+fn update_intensity(
+    mut intensity: ResMut<MusicIntensity>,
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    players: Query<&Health>,
+) {
+    let target = match state.get() {
+        GameState::Playing => players
+            .iter()
+            .map(|health| 1.0 - health.fraction().clamp(0.0, 1.0))
+            .fold(0.0, f32::max),
+        GameState::MainMenu | GameState::Lobby => 0.0,
+    };
+    let t = 1.0 - (-INTENSITY_SMOOTHING * time.delta_secs()).exp();
+    intensity.0 = intensity.0.lerp(target, t);
+}
+
+fn fade_layers(
+    mut commands: Commands,
+    time: Res<Time>,
+    volumes: Res<AudioVolumes>,
+    intensity: Res<MusicIntensity>,
+    layers: Query<(Entity, &mut MusicLayer, Option<&mut AudioSink>)>,
+) {
+    let step = time.delta_secs() / FADE_TIME;
+    let t = 1.0 - (-CUE_SMOOTHING * time.delta_secs()).exp();
+    for (entity, mut layer, sink) in layers {
+        layer.speed = layer.speed.lerp(layer.cue.speed, t);
+        layer.volume = layer.volume.lerp(layer.cue.volume, t);
+
+        if layer.fading_out {
+            layer.gain -= step;
+            if layer.gain <= 0.0 {
+                commands.entity(entity).despawn();
+                continue;
+            }
+        } else {
+            layer.gain = (layer.gain + step).min(1.0);
+        }
+
+        // Still loading.
+        let Some(mut sink) = sink else {
+            continue;
+        };
+        let volume = layer.gain * layer.volume * (1.0 + intensity.0 * INTENSITY_BOOST);
+        sink.set_volume(volumes.music() * Volume::Linear(volume));
+        sink.set_speed(layer.speed + intensity.0 * INTENSITY_SPEEDUP);
+    }
+}
@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+
+use crate::Player;
+use crate::tilemap::MapBounds;
+
+pub mod effects;
+pub mod free_camera;
+pub mod split_screen;
+
+/// UI is drawn by its own camera, so it covers the whole window however the world cameras are
+/// split up.
+const UI_CAMERA_ORDER: isize = 10;
+
+/// Who a [`CameraRig`] keeps in view.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraTarget {
+    AllPlayers,
+    Player(Entity),
+}
+
+/// Keeps its target in view: eases toward the middle of the players it follows, zooms out as they
+/// spread apart, and never shows what is past the edge of the map.
+#[derive(Component)]
+pub struct CameraRig {
+    pub target: CameraTarget,
+    /// How quickly the camera catches up, higher is snappier.
+    pub smoothing: f32,
+    /// World space kept free around the outermost players.
+    pub margin: Vec2,
+    /// Smallest `OrthographicProjection::scale`, i.e. the closest the camera zooms in.
+    pub min_zoom: f32,
+    /// Largest `OrthographicProjection::scale`, i.e. the furthest the camera zooms out.
+    pub max_zoom: f32,
+    /// Where the rig is looking, before any effects like shake move the camera.
+    focus: Vec2,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self {
+            target: CameraTarget::AllPlayers,
+            smoothing: 4.0,
+            margin: Vec2::new(200.0, 150.0),
+            min_zoom: 1.0,
+            max_zoom: 3.0,
+            focus: Vec2::ZERO,
+        }
+    }
+}
+
+impl CameraRig {
+    /// A rig that starts out already looking at `focus`, instead of sweeping over from the origin.
+    pub fn looking_at(target: CameraTarget, focus: Vec2) -> Self {
+        Self {
+            target,
+            focus,
+            ..default()
+        }
+    }
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            effects::CameraEffectsPlugin,
+            free_camera::FreeCameraPlugin,
+            split_screen::SplitScreenPlugin,
+        ))
+        .add_systems(Startup, spawn_camera)
+        .add_systems(Update, camera_follow_player);
+    }
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2d,
+        CameraRig::default(),
+        effects::CameraShake::default(),
+    ));
+
+    // Only draws UI, it sees none of the world's render layers.
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order: UI_CAMERA_ORDER,
+            clear_color: ClearColorConfig::None,
+            ..default()
+        },
+        RenderLayers::layer(31),
+        IsDefaultUiCamera,
+    ));
+}
+
+fn camera_follow_player(
+    players: Query<(Entity, &Transform), (With<Player>, Without<CameraRig>)>,
+    cameras: Query<(&mut Transform, &mut Projection, &Camera, &mut CameraRig), Without<Player>>,
+    map_bounds: Option<Res<MapBounds>>,
+    time: Res<Time>,
+) {
+    for (mut transform, mut projection, camera, mut rig) in cameras {
+        let mut min = Vec2::INFINITY;
+        let mut max = Vec2::NEG_INFINITY;
+        for (player, player_transform) in &players {
+            if rig.target == CameraTarget::AllPlayers || rig.target == CameraTarget::Player(player)
+            {
+                min = min.min(player_transform.translation.xy());
+                max = max.max(player_transform.translation.xy());
+            }
+        }
+
+        let Projection::Orthographic(projection) = &mut *projection else {
+            continue;
+        };
+        let Some(viewport) = camera.logical_viewport_size() else {
+            continue;
+        };
+
+        // With nobody around, hold still where we are.
+        let (target, target_zoom) = if min.x <= max.x {
+            let needed = (max - min) + rig.margin * 2.0;
+            let zoom = (needed / viewport).max_element();
+            ((min + max) / 2.0, zoom.clamp(rig.min_zoom, rig.max_zoom))
+        } else {
+            (rig.focus, projection.scale)
+        };
+
+        // Frame rate independent exponential easing.
+        let t = 1.0 - (-rig.smoothing * time.delta_secs()).exp();
+        let mut position = rig.focus.lerp(target, t);
+        let mut zoom = projection.scale.lerp(target_zoom, t);
+
+        if let Some(MapBounds(bounds)) = map_bounds.as_deref() {
+            // Zooming out further than the whole map only shows the void around it.
+            let fit_map = (bounds.size() / viewport).min_element();
+            zoom = zoom.min(fit_map.max(rig.min_zoom));
+            position = clamp_view(position, viewport * zoom / 2.0, *bounds);
+        }
+
+        rig.focus = position;
+        transform.translation = position.extend(transform.translation.z);
+        projection.scale = zoom;
+    }
+}
+
+/// Moves the center of a view with the given half size so the view stays inside `bounds`,
+/// centering it on any axis where it can't fit.
+fn clamp_view(center: Vec2, half_size: Vec2, bounds: Rect) -> Vec2 {
+    let clamp_axis = |center: f32, half: f32, min: f32, max: f32| {
+        if max - min <= half * 2.0 {
+            (min + max) / 2.0
+        } else {
+            center.clamp(min + half, max - half)
+        }
+    };
+    Vec2::new(
+        clamp_axis(center.x, half_size.x, bounds.min.x, bounds.max.x),
+        clamp_axis(center.y, half_size.y, bounds.min.y, bounds.max.y),
+    )
+}
@@ -0,0 +1,140 @@
+//! Screen shake and hit-stop, so hits and slams feel like they land.
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::impact::{ImpactEvent, ImpactKind};
+
+/// An impact this strong adds a full unit of trauma.
+const FULL_TRAUMA_IMPACT: f32 = 3000.0;
+/// Impacts weaker than this don't freeze the game.
+const MIN_HIT_STOP_IMPACT: f32 = 1200.0;
+/// Trauma lost per second.
+const TRAUMA_DECAY: f32 = 1.5;
+/// Offset of the camera at full trauma, in logical pixels at zoom 1.
+const MAX_SHAKE_OFFSET: f32 = 24.0;
+/// Roll of the camera at full trauma, in radians.
+const MAX_SHAKE_ROLL: f32 = 0.05;
+/// Relative physics speed while frozen.
+const HIT_STOP_SPEED: f32 = 0.05;
+
+#[derive(Resource)]
+pub struct CameraEffectsSettings {
+    /// Turns off both shake and hit-stop, for players who get motion sick.
+    pub enabled: bool,
+    /// Scales how much trauma impacts add. 1 is the default, 0 turns shake off.
+    pub shake_intensity: f32,
+    /// Longest freeze, in real seconds, for the strongest impacts. 0 turns hit-stop off.
+    pub max_hit_stop: f32,
+}
+
+impl Default for CameraEffectsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            shake_intensity: 1.0,
+            max_hit_stop: 0.08,
+        }
+    }
+}
+
+/// Trauma based screen shake. The shake grows with the square of the trauma, so small hits are
+/// subtle and big ones feel violent.
+#[derive(Component, Default)]
+pub struct CameraShake {
+    trauma: f32,
+}
+
+/// Physics is slowed down until `remaining` real seconds have passed.
+#[derive(Resource, Default)]
+struct HitStop {
+    remaining: f32,
+}
+
+pub struct CameraEffectsPlugin;
+
+impl Plugin for CameraEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraEffectsSettings>()
+            .init_resource::<HitStop>()
+            .add_systems(Update, (react_to_impacts, tick_hit_stop).chain())
+            .add_systems(
+                PostUpdate,
+                shake.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+fn react_to_impacts(
+    mut impacts: EventReader<ImpactEvent>,
+    settings: Res<CameraEffectsSettings>,
+    mut hit_stop: ResMut<HitStop>,
+    cameras: Query<&mut CameraShake>,
+) {
+    if !settings.enabled {
+        impacts.clear();
+        return;
+    }
+
+    let mut trauma = 0.0;
+    let mut strongest = 0.0_f32;
+    for impact in impacts.read().filter(|impact| !impact.resimulated) {
+        // Slams are expected and frequent, don't let them drown out the hits.
+        let weight = match impact.kind {
+            ImpactKind::Whip | ImpactKind::Throw => 1.0,
+            ImpactKind::Slam => 0.5,
+        };
+        trauma += weight * impact.strength / FULL_TRAUMA_IMPACT;
+        strongest = strongest.max(weight * impact.strength);
+    }
+
+    for mut shake in cameras {
+        shake.trauma = (shake.trauma + trauma * settings.shake_intensity).min(1.0);
+    }
+
+    if strongest >= MIN_HIT_STOP_IMPACT {
+        let duration = settings.max_hit_stop * (strongest / FULL_TRAUMA_IMPACT).min(1.0);
+        hit_stop.remaining = hit_stop.remaining.max(duration);
+    }
+}
+
+fn tick_hit_stop(
+    mut hit_stop: ResMut<HitStop>,
+    real_time: Res<Time<Real>>,
+    mut physics_time: ResMut<Time<Physics>>,
+) {
+    if hit_stop.remaining > 0.0 {
+        hit_stop.remaining -= real_time.delta_secs();
+        physics_time.set_relative_speed(HIT_STOP_SPEED);
+    } else if physics_time.relative_speed() != 1.0 {
+        physics_time.set_relative_speed(1.0);
+    }
+}
+
+/// Runs after the [`CameraRig`](super::CameraRig) placed the camera, and offsets it from there.
+fn shake(time: Res<Time>, cameras: Query<(&mut CameraShake, &mut Transform, &Projection)>) {
+    let t = time.elapsed_secs();
+    for (mut shake, mut transform, projection) in cameras {
+        let amount = shake.trauma * shake.trauma;
+        let zoom = match projection {
+            Projection::Orthographic(projection) => projection.scale,
+            _ => 1.0,
+        };
+
+        transform.translation +=
+            (Vec2::new(noise(t, 0.0), noise(t, 10.0)) * MAX_SHAKE_OFFSET * zoom * amount)
+                .extend(0.0);
+        transform.rotation = Quat::from_rotation_z(noise(t, 20.0) * MAX_SHAKE_ROLL * amount);
+
+        shake.trauma = (shake.trauma - TRAUMA_DECAY * time.delta_secs()).max(0.0);
+    }
+}
+
+/// Cheap smooth noise in -1..1, different for each `seed`.
+fn noise(t: f32, seed: f32) -> f32 {
+    ((t * 37.0 + seed).sin()
+        + (t * 61.0 + seed * 1.7).sin() * 0.6
+        + (t * 89.0 + seed * 2.3).sin() * 0.3)
+        / 1.9
+}
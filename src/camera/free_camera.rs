@@ -0,0 +1,222 @@
+//! A debug camera that flies around the level on its own, and the avian physics debug overlay.
+//!
+//! * `F2` toggles the free camera.
+//! * Mouse: middle or right drag to pan, scroll to zoom, left click to follow the closest player
+//!   or chain link.
+//! * Keyboard: `I` `J` `K` `L` to pan (hold shift for faster), `U` `O` to zoom, `Tab` to cycle
+//!   through players and their chain tips.
+//! * `F3` toggles the physics debug overlay, with or without the free camera.
+
+use avian2d::prelude::*;
+use bevy::input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll, MouseScrollUnit};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use super::CameraRig;
+use crate::player::PlayerNumber;
+use crate::{Chain, ChainLink, Player};
+
+/// Above every world camera, below the UI.
+const FREE_CAMERA_ORDER: isize = 5;
+const PAN_SPEED: f32 = 800.0;
+const ZOOM_SPEED: f32 = 1.5;
+const MIN_ZOOM: f32 = 0.2;
+const MAX_ZOOM: f32 = 10.0;
+/// How close, in world units at zoom 1, a click has to be to something to follow it.
+const PICK_RADIUS: f32 = 80.0;
+
+#[derive(Component, Default)]
+pub struct FreeCamera {
+    /// Player or chain link we stick to, until the camera is panned by hand.
+    pub follow: Option<Entity>,
+}
+
+pub struct FreeCameraPlugin;
+
+impl Plugin for FreeCameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PhysicsDebugPlugin::default())
+            .add_systems(Startup, hide_physics_debug)
+            .add_systems(
+                Update,
+                (
+                    toggle_free_camera,
+                    toggle_physics_debug,
+                    (pick_follow_target, move_free_camera).chain(),
+                ),
+            );
+    }
+}
+
+fn hide_physics_debug(mut config_store: ResMut<GizmoConfigStore>) {
+    config_store.config_mut::<PhysicsGizmos>().0.enabled = false;
+}
+
+fn toggle_physics_debug(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut config_store: ResMut<GizmoConfigStore>,
+) {
+    if keyboard.just_pressed(KeyCode::F3) {
+        let config = config_store.config_mut::<PhysicsGizmos>().0;
+        config.enabled = !config.enabled;
+    }
+}
+
+fn toggle_free_camera(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    free_cameras: Query<Entity, With<FreeCamera>>,
+    rigs: Query<(&Transform, &Projection, &Camera), With<CameraRig>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F2) {
+        return;
+    }
+
+    if !free_cameras.is_empty() {
+        for camera in &free_cameras {
+            commands.entity(camera).despawn();
+        }
+        return;
+    }
+
+    // Take off from wherever the game camera is looking.
+    let (transform, projection) = rigs
+        .iter()
+        .find(|(_, _, camera)| camera.is_active)
+        .map(|(transform, projection, _)| (*transform, projection.clone()))
+        .unwrap_or((
+            Transform::default(),
+            Projection::Orthographic(OrthographicProjection::default_2d()),
+        ));
+    commands.spawn((
+        Camera2d,
+        Camera {
+            order: FREE_CAMERA_ORDER,
+            ..default()
+        },
+        FreeCamera::default(),
+        transform.with_rotation(Quat::IDENTITY),
+        projection,
+    ));
+}
+
+fn pick_follow_target(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    free_camera: Option<Single<(&mut FreeCamera, &Camera, &GlobalTransform, &Projection)>>,
+    players: Query<(Entity, &PlayerNumber, &Chain), With<Player>>,
+    bodies: Query<(Entity, &GlobalTransform), Or<(With<Player>, With<ChainLink>)>>,
+) {
+    let Some(free_camera) = free_camera else {
+        return;
+    };
+    let (mut free_camera, camera, camera_transform, projection) = free_camera.into_inner();
+
+    if keyboard.just_pressed(KeyCode::Tab) {
+        let mut players: Vec<_> = players.iter().collect();
+        players.sort_by_key(|(_, number, _)| number.0);
+        let targets: Vec<Entity> = players
+            .into_iter()
+            .flat_map(|(player, _, chain)| [Some(player), chain.links.last().copied()])
+            .flatten()
+            .collect();
+
+        let next = free_camera
+            .follow
+            .and_then(|current| targets.iter().position(|target| *target == current))
+            .map_or(0, |index| index + 1);
+        free_camera.follow = targets.get(next).copied();
+    }
+
+    if mouse.just_pressed(MouseButton::Left) {
+        let Some(cursor) = window
+            .cursor_position()
+            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
+        else {
+            return;
+        };
+        let zoom = match projection {
+            Projection::Orthographic(projection) => projection.scale,
+            _ => 1.0,
+        };
+
+        free_camera.follow = bodies
+            .iter()
+            .map(|(entity, transform)| (entity, transform.translation().xy().distance(cursor)))
+            .filter(|(_, distance)| *distance <= PICK_RADIUS * zoom)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(entity, _)| entity);
+    }
+}
+
+fn move_free_camera(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+    time: Res<Time<Real>>,
+    free_camera: Option<Single<(&mut FreeCamera, &mut Transform, &mut Projection)>>,
+    targets: Query<&GlobalTransform, Without<FreeCamera>>,
+) {
+    let Some(free_camera) = free_camera else {
+        return;
+    };
+    let (mut free_camera, mut transform, mut projection) = free_camera.into_inner();
+    let Projection::Orthographic(projection) = &mut *projection else {
+        return;
+    };
+    // Real time, so the camera still moves while the game is paused or in hit-stop.
+    let dt = time.delta_secs();
+
+    let mut zoom_steps = -mouse_scroll.delta.y
+        * match mouse_scroll.unit {
+            MouseScrollUnit::Line => 0.1,
+            MouseScrollUnit::Pixel => 0.005,
+        };
+    if keyboard.pressed(KeyCode::KeyU) {
+        zoom_steps -= dt;
+    }
+    if keyboard.pressed(KeyCode::KeyO) {
+        zoom_steps += dt;
+    }
+    projection.scale = (projection.scale * ZOOM_SPEED.powf(zoom_steps)).clamp(MIN_ZOOM, MAX_ZOOM);
+
+    let mut pan = Vec2::ZERO;
+    if keyboard.pressed(KeyCode::KeyJ) {
+        pan.x -= 1.0;
+    }
+    if keyboard.pressed(KeyCode::KeyL) {
+        pan.x += 1.0;
+    }
+    if keyboard.pressed(KeyCode::KeyK) {
+        pan.y -= 1.0;
+    }
+    if keyboard.pressed(KeyCode::KeyI) {
+        pan.y += 1.0;
+    }
+    let speed = if keyboard.pressed(KeyCode::ShiftLeft) {
+        PAN_SPEED * 3.0
+    } else {
+        PAN_SPEED
+    };
+    pan *= speed * dt;
+    if mouse.any_pressed([MouseButton::Middle, MouseButton::Right]) {
+        // Screen y points down, world y up.
+        pan += mouse_motion.delta * Vec2::new(-1.0, 1.0);
+    }
+    pan *= projection.scale;
+
+    if pan != Vec2::ZERO {
+        free_camera.follow = None;
+        transform.translation += pan.extend(0.0);
+    } else if let Some(target) = free_camera.follow {
+        match targets.get(target) {
+            Ok(target) => {
+                transform.translation = target.translation().xy().extend(transform.translation.z);
+            }
+            // Whatever we followed is gone.
+            Err(_) => free_camera.follow = None,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Gives every player their own camera and a section of the window once they are too far apart
+//! to share one.
+
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
+
+use super::effects::CameraShake;
+use super::{CameraRig, CameraTarget};
+use crate::Player;
+use crate::player::PlayerNumber;
+
+#[derive(Resource)]
+pub struct SplitScreenSettings {
+    /// Turns splitting off altogether, everyone always shares the one camera.
+    pub enabled: bool,
+    /// Split once two players are further apart than this.
+    pub split_distance: f32,
+    /// Go back to a shared camera once all players are closer than this. Lower than
+    /// `split_distance`, so we don't flip back and forth around the threshold.
+    pub merge_distance: f32,
+}
+
+impl Default for SplitScreenSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            split_distance: 1800.0,
+            merge_distance: 1300.0,
+        }
+    }
+}
+
+/// A camera following one player while the screen is split.
+#[derive(Component)]
+pub struct PlayerCamera(pub Entity);
+
+pub struct SplitScreenPlugin;
+
+impl Plugin for SplitScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplitScreenSettings>().add_systems(
+            Update,
+            (switch_screen_mode, layout_viewports)
+                .chain()
+                .before(super::camera_follow_player),
+        );
+    }
+}
+
+fn switch_screen_mode(
+    mut commands: Commands,
+    settings: Res<SplitScreenSettings>,
+    players: Query<(Entity, &Transform, &PlayerNumber), With<Player>>,
+    shared_cameras: Query<(&mut Camera, &CameraRig), Without<PlayerCamera>>,
+    player_cameras: Query<(Entity, &PlayerCamera)>,
+) {
+    let is_split = !player_cameras.is_empty();
+    let spread = players
+        .iter()
+        .flat_map(|(_, a, _)| {
+            players
+                .iter()
+                .map(move |(_, b, _)| a.translation.distance(b.translation))
+        })
+        .fold(0.0, f32::max);
+
+    let should_split = settings.enabled
+        && players.iter().count() > 1
+        && if is_split {
+            spread > settings.merge_distance
+        } else {
+            spread > settings.split_distance
+        };
+
+    // Every player needs their own camera, and nobody who left should keep one.
+    let cameras_match_players = player_cameras.iter().count() == players.iter().count()
+        && player_cameras
+            .iter()
+            .all(|(_, PlayerCamera(player))| players.contains(*player));
+    if should_split == is_split && (!is_split || cameras_match_players) {
+        return;
+    }
+
+    for (camera, _) in &player_cameras {
+        commands.entity(camera).despawn();
+    }
+
+    // The shared camera keeps following everyone while inactive, so merging doesn't jump.
+    for (mut camera, rig) in shared_cameras {
+        if rig.target == CameraTarget::AllPlayers {
+            camera.is_active = !should_split;
+        }
+    }
+
+    if should_split {
+        info!("Splitting the screen, players are {spread:.0} apart");
+        let mut players: Vec<_> = players.iter().collect();
+        players.sort_by_key(|(_, _, number)| number.0);
+        for (order, (player, transform, _)) in players.into_iter().enumerate() {
+            commands.spawn((
+                Camera2d,
+                Camera {
+                    order: order as isize,
+                    ..default()
+                },
+                PlayerCamera(player),
+                CameraRig {
+                    max_zoom: 1.5,
+                    ..CameraRig::looking_at(
+                        CameraTarget::Player(player),
+                        transform.translation.xy(),
+                    )
+                },
+                CameraShake::default(),
+            ));
+        }
+    } else {
+        info!("Players are back together, sharing one camera");
+    }
+}
+
+/// Sizes every player camera's section of the window: side by side for two players, two on top
+/// and one below for three, and quadrants for four.
+fn layout_viewports(
+    window: Single<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&mut Camera, &PlayerCamera)>,
+    numbers: Query<&PlayerNumber>,
+) {
+    let mut cameras: Vec<_> = cameras.into_iter().collect();
+    cameras
+        .sort_by_key(|(_, PlayerCamera(player))| numbers.get(*player).map_or(usize::MAX, |n| n.0));
+
+    let count = cameras.len();
+    let size = window.physical_size();
+    let half = size / 2;
+    for (index, (mut camera, _)) in cameras.into_iter().enumerate() {
+        let (position, section) = match (count, index) {
+            (1, _) => (UVec2::ZERO, size),
+            (2, i) => (UVec2::new(i as u32 * half.x, 0), UVec2::new(half.x, size.y)),
+            (3, 2) => (UVec2::new(0, half.y), UVec2::new(size.x, half.y)),
+            (_, i) => (
+                UVec2::new((i as u32 % 2) * half.x, (i as u32 / 2) * half.y),
+                half,
+            ),
+        };
+
+        let section = section.max(UVec2::ONE);
+        let unchanged = camera.viewport.as_ref().is_some_and(|viewport| {
+            viewport.physical_position == position && viewport.physical_size == section
+        });
+        if !unchanged {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: section,
+                ..default()
+            });
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Changing chains after [`spawn_chain`](crate::spawn_chain) built them: growing them, shortening
+//! them and cutting them in two.
+//!
+//! Everything goes through [`ChainEditor`], which keeps [`Chain`] in step with the links and
+//! joints it spawns and despawns. A piece cut off a chain keeps its joints and flops around as a
+//! [`LooseChain`] for a while before it disappears.
+
+use std::time::Duration;
+
+use avian2d::prelude::*;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+use crate::delete_after::{Clock, DeleteAt, Despawner, SpawnedInMatch};
+use crate::player::Appearance;
+use crate::{Chain, ChainLink, spawn_link, spawn_link_joint};
+
+/// However many links are added, a chain never gets longer than this.
+pub const MAX_CHAIN_LINKS: usize = 16;
+/// Nor shorter than this, there has to be something left to whip with.
+pub const MIN_CHAIN_LINKS: usize = 3;
+/// How long a piece cut off a chain lies around.
+const LOOSE_CHAIN_LIFETIME: Duration = Duration::from_secs(6);
+const LOOSE_CHAIN_FADE: Duration = Duration::from_secs(1);
+
+/// A piece cut off a chain. Its links are its children, so it goes away in one piece.
+#[derive(Component)]
+pub struct LooseChain {
+    /// In the order they were in on the chain, towards what was the tip.
+    pub links: Vec<Entity>,
+    pub color: Color,
+}
+
+/// Grows, shrinks and cuts chains.
+#[derive(SystemParam)]
+pub struct ChainEditor<'w, 's> {
+    commands: Commands<'w, 's>,
+    chains: Query<'w, 's, (&'static mut Chain, &'static Appearance)>,
+    links: Query<'w, 's, (&'static ChainLink, &'static Transform)>,
+    joints: Query<'w, 's, &'static RevoluteJoint>,
+    despawner: Despawner<'w, 's>,
+}
+
+impl ChainEditor<'_, '_> {
+    /// Adds up to `count` links past the tip of the chain of `player`, as long as it stays within
+    /// [`MAX_CHAIN_LINKS`]. Returns how many were added.
+    pub fn append(&mut self, player: Entity, count: usize) -> usize {
+        let Ok((mut chain, _)) = self.chains.get_mut(player) else {
+            return 0;
+        };
+        // The tip has to have been around for a tick, to know where to put the new links.
+        let Some((_, tip)) = chain.links.last().and_then(|tip| self.links.get(*tip).ok()) else {
+            return 0;
+        };
+
+        let count = count.min(MAX_CHAIN_LINKS.saturating_sub(chain.links.len()));
+        for i in 0..count {
+            let previous = chain.links[chain.links.len() - 1];
+            // Lined up past the tip the way the joints want them, so they don't get flung apart.
+            let translation = tip.translation + tip.rotation * Vec3::NEG_Y * 20.0 * (i + 1) as f32;
+            let link = spawn_link(&mut self.commands, player, translation);
+            let joint = spawn_link_joint(&mut self.commands, previous, link);
+            self.commands.entity(link).insert(SpawnedInMatch);
+            self.commands.entity(joint).insert(SpawnedInMatch);
+            chain.joints.push(joint);
+            chain.links.push(link);
+        }
+        count
+    }
+
+    /// Takes up to `count` links off the tip of the chain of `player`, as long as at least
+    /// [`MIN_CHAIN_LINKS`] are left. Returns how many were removed.
+    pub fn remove(&mut self, player: Entity, count: usize) -> usize {
+        for removed in 0..count {
+            let tip = self
+                .chains
+                .get(player)
+                .ok()
+                .and_then(|(chain, _)| chain.links.last().copied());
+            if !tip.is_some_and(|tip| self.remove_at(tip)) {
+                return removed;
+            }
+        }
+        count
+    }
+
+    /// Takes `link` out of its chain and joins the links on either side of it, as long as at
+    /// least [`MIN_CHAIN_LINKS`] are left. The link attached to the player stays. Returns whether
+    /// it was removed.
+    pub fn remove_at(&mut self, link: Entity) -> bool {
+        let Some((mut chain, _)) = self
+            .chains
+            .iter_mut()
+            .find(|(chain, _)| chain.links.contains(&link))
+        else {
+            return false;
+        };
+        let Some(at) = chain.links.iter().position(|other| *other == link) else {
+            return false;
+        };
+        if at == 0 || chain.links.len() <= MIN_CHAIN_LINKS {
+            return false;
+        }
+
+        chain.links.remove(at);
+        // Takes its joints to the links on either side along, and anything else holding on to it.
+        self.despawner.despawn(link);
+        let joints = &self.joints;
+        chain.joints.retain(|joint| {
+            !joints
+                .get(*joint)
+                .is_ok_and(|joint| joint.entity1 == link || joint.entity2 == link)
+        });
+        if let Some(next) = chain.links.get(at).copied() {
+            let joint = spawn_link_joint(&mut self.commands, chain.links[at - 1], next);
+            self.commands.entity(joint).insert(SpawnedInMatch);
+            chain.joints.push(joint);
+        }
+        true
+    }
+
+    /// Grows or shrinks the chain of `player` to `length` links.
+    pub fn set_length(&mut self, player: Entity, length: usize) {
+        let Ok((chain, _)) = self.chains.get(player) else {
+            return;
+        };
+        let current = chain.links.len();
+        if length > current {
+            self.append(player, length - current);
+        } else {
+            self.remove(player, current - length);
+        }
+    }
+
+    /// Where `link` is on its chain, 0 being the one attached to the player.
+    pub fn link_index(&self, link: Entity) -> Option<usize> {
+        let (ChainLink { player }, _) = self.links.get(link).ok()?;
+        let (chain, _) = self.chains.get(*player).ok()?;
+        chain.links.iter().position(|other| *other == link)
+    }
+
+    /// Cuts the chain `link` is part of right before it, leaving it and every link past it as a
+    /// [`LooseChain`]. Cutting any closer to the player than [`MIN_CHAIN_LINKS`] cuts right after
+    /// those instead. Returns the loose piece, if anything was cut.
+    pub fn sever(&mut self, link: Entity) -> Option<Entity> {
+        let at = self.link_index(link)?.max(MIN_CHAIN_LINKS);
+        let (ChainLink { player }, _) = self.links.get(link).ok()?;
+        let (mut chain, appearance) = self.chains.get_mut(*player).ok()?;
+        if at >= chain.links.len() {
+            return None;
+        }
+
+        let loose = chain.links.split_off(at);
+        let cut = chain.links[at - 1];
+        // The joint that was holding the piece on goes, the ones between its links stay with it.
+        let mut kept = Vec::with_capacity(chain.joints.len());
+        for joint in std::mem::take(&mut chain.joints) {
+            match self.joints.get(joint) {
+                Ok(revolute) if revolute.entity1 == cut && revolute.entity2 == loose[0] => {
+                    self.despawner.despawn(joint);
+                }
+                Ok(revolute) if loose.contains(&revolute.entity1) => {}
+                _ => kept.push(joint),
+            }
+        }
+        chain.joints = kept;
+
+        let color = appearance.color();
+        let piece = self
+            .commands
+            .spawn((
+                LooseChain {
+                    links: loose.clone(),
+                    color,
+                },
+                // At the origin, so the links stay where they are in the world.
+                Transform::default(),
+                Visibility::default(),
+                DeleteAt::after(LOOSE_CHAIN_LIFETIME)
+                    .fading_out(LOOSE_CHAIN_FADE)
+                    .on_clock(Clock::Fixed),
+                SpawnedInMatch,
+            ))
+            .id();
+        for link in loose {
+            // No longer part of a whip, so it hits nothing and can't pick anything up.
+            self.commands
+                .entity(link)
+                .remove::<ChainLink>()
+                .insert(ChildOf(piece));
+        }
+        Some(piece)
+    }
+}
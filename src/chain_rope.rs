@@ -0,0 +1,302 @@
+//! Draws every chain as one continuous rope instead of a sprite per link.
+//!
+//! The rope is a triangle strip rebuilt every frame through the links of the chain, smoothed
+//! between them, with `chain.png` repeating along its length. When the tip moves fast it also
+//! drags a fading ribbon behind it. Pieces cut off a chain are drawn the same way until they fade.
+
+use std::collections::VecDeque;
+
+use bevy::image::{ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+
+use crate::chain::LooseChain;
+use crate::delete_after::DeleteAt;
+use crate::player::{ABSENT_ALPHA, Absent, Appearance};
+use crate::{Chain, ChainBase, Player};
+
+const ROPE_WIDTH: f32 = 12.0;
+/// Length of rope one repeat of `chain.png` covers.
+const TEXTURE_LENGTH: f32 = 24.0;
+/// Points added between two links to round off the corners.
+const SUBDIVISIONS: usize = 4;
+/// Behind the players.
+const ROPE_Z: f32 = -0.1;
+
+/// The ribbon starts to show when the tip moves this fast relative to its player.
+const RIBBON_MIN_SPEED: f32 = 800.0;
+/// And is fully opaque from this speed on.
+const RIBBON_FULL_SPEED: f32 = 2000.0;
+/// How many past tip positions the ribbon runs through, one per frame.
+const RIBBON_LENGTH: usize = 10;
+const RIBBON_WIDTH: f32 = 18.0;
+
+/// Mesh drawing `chain`, the player holding it or a [`LooseChain`].
+#[derive(Component)]
+struct ChainVisual {
+    chain: Entity,
+}
+
+/// Marks the [`ChainVisual`] that is the motion blur behind the tip rather than the rope.
+#[derive(Component, Default)]
+struct TipRibbon {
+    /// Newest first.
+    trail: VecDeque<Vec2>,
+}
+
+pub struct ChainRopePlugin;
+
+impl Plugin for ChainRopePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                spawn_chain_visuals,
+                update_ropes,
+                update_ribbons,
+                despawn_orphans,
+            ),
+        );
+    }
+}
+
+fn spawn_chain_visuals(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    chains: Query<(Entity, &Appearance), Added<Chain>>,
+    loose: Query<(Entity, &LooseChain), Added<LooseChain>>,
+) {
+    let colors = chains
+        .iter()
+        .map(|(chain, appearance)| (chain, appearance.color()))
+        .chain(loose.iter().map(|(chain, loose)| (chain, loose.color)));
+    for (chain, color) in colors {
+        // Repeats along the rope instead of stretching over it.
+        let texture =
+            asset_server.load_with_settings("chain.png", |settings: &mut ImageLoaderSettings| {
+                settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                    address_mode_v: ImageAddressMode::Repeat,
+                    ..default()
+                });
+            });
+
+        commands.spawn((
+            ChainVisual { chain },
+            Mesh2d(meshes.add(empty_mesh())),
+            MeshMaterial2d(materials.add(ColorMaterial {
+                color,
+                texture: Some(texture),
+                ..default()
+            })),
+            Transform::from_xyz(0.0, 0.0, ROPE_Z),
+            // Until there is a mesh to show.
+            Visibility::Hidden,
+        ));
+    }
+
+    // Only held chains crack.
+    for (player, appearance) in chains {
+        commands.spawn((
+            ChainVisual { chain: player },
+            TipRibbon::default(),
+            Mesh2d(meshes.add(empty_mesh())),
+            MeshMaterial2d(materials.add(appearance.color().lighter(0.3))),
+            Transform::from_xyz(0.0, 0.0, ROPE_Z - 0.01),
+            Visibility::Hidden,
+        ));
+    }
+}
+
+fn empty_mesh() -> Mesh {
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::default(),
+    )
+}
+
+fn update_ropes(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    ropes: Query<
+        (
+            &ChainVisual,
+            &Mesh2d,
+            &MeshMaterial2d<ColorMaterial>,
+            &mut Visibility,
+        ),
+        Without<TipRibbon>,
+    >,
+    players: Query<(&Chain, &Transform, Has<Absent>), With<Player>>,
+    loose: Query<(&LooseChain, Option<&DeleteAt>)>,
+    bases: Query<&ChainBase>,
+    links: Query<&Transform, Without<Player>>,
+) {
+    let link_positions = |chain: &[Entity]| -> Vec<Vec2> {
+        chain
+            .iter()
+            .filter_map(|link| links.get(*link).ok())
+            .map(|transform| transform.translation.xy())
+            .collect()
+    };
+
+    for (visual, mesh, material, mut visibility) in ropes {
+        let (points, alpha) =
+            if let Ok((chain, player_transform, absent)) = players.get(visual.chain) {
+                // From where the chain is attached to the player, through every link.
+                let base = bases
+                    .get(chain.base)
+                    .map(|base| base.getPos())
+                    .unwrap_or_default();
+                let mut points = vec![player_transform.translation.xy() + base];
+                points.extend(link_positions(&chain.links));
+                // Fade along with the player while their pad is gone.
+                (points, if absent { ABSENT_ALPHA } else { 1.0 })
+            } else if let Ok((loose, delete_at)) = loose.get(visual.chain) {
+                let alpha = delete_at.map_or(1.0, DeleteAt::opacity);
+                (link_positions(&loose.links), alpha)
+            } else {
+                continue;
+            };
+        let Some(mesh) = meshes.get_mut(&mesh.0) else {
+            continue;
+        };
+        let points = smooth(&points);
+
+        let mut length = 0.0;
+        let mut uvs = Vec::with_capacity(points.len() * 2);
+        for (i, point) in points.iter().enumerate() {
+            if i > 0 {
+                length += point.distance(points[i - 1]);
+            }
+            let v = length / TEXTURE_LENGTH;
+            uvs.extend([[0.0, v], [1.0, v]]);
+        }
+        set_strip(mesh, &points, |_| ROPE_WIDTH / 2.0);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        *visibility = Visibility::Inherited;
+
+        let faded = materials
+            .get(&material.0)
+            .is_some_and(|material| material.color.alpha() != alpha);
+        if faded && let Some(material) = materials.get_mut(&material.0) {
+            material.color.set_alpha(alpha);
+        }
+    }
+}
+
+fn update_ribbons(
+    mut meshes: ResMut<Assets<Mesh>>,
+    ribbons: Query<(&ChainVisual, &mut TipRibbon, &Mesh2d, &mut Visibility)>,
+    players: Query<(&Chain, &Transform), With<Player>>,
+    links: Query<&Transform, Without<Player>>,
+    time: Res<Time>,
+) {
+    for (visual, mut ribbon, mesh, mut visibility) in ribbons {
+        let Ok((chain, player_transform)) = players.get(visual.chain) else {
+            continue;
+        };
+        let Some(tip) = chain.links.last().and_then(|tip| links.get(*tip).ok()) else {
+            continue;
+        };
+        let Some(mesh) = meshes.get_mut(&mesh.0) else {
+            continue;
+        };
+
+        // Positions relative to the player, so walking alone doesn't leave a ribbon.
+        let tip = tip.translation.xy() - player_transform.translation.xy();
+        let speed = ribbon
+            .trail
+            .front()
+            .map(|last| tip.distance(*last) / time.delta_secs().max(f32::EPSILON))
+            .unwrap_or_default();
+        ribbon.trail.push_front(tip);
+        ribbon.trail.truncate(RIBBON_LENGTH);
+
+        let intensity =
+            ((speed - RIBBON_MIN_SPEED) / (RIBBON_FULL_SPEED - RIBBON_MIN_SPEED)).clamp(0.0, 1.0);
+        if intensity == 0.0 || ribbon.trail.len() < 2 {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        *visibility = Visibility::Inherited;
+
+        let points: Vec<Vec2> = ribbon
+            .trail
+            .iter()
+            .map(|point| *point + player_transform.translation.xy())
+            .collect();
+        let points = smooth(&points);
+        let fade = |i: usize| 1.0 - i as f32 / (points.len() - 1) as f32;
+        let colors: Vec<[f32; 4]> = (0..points.len())
+            .flat_map(|i| {
+                let alpha = intensity * fade(i);
+                [[1.0, 1.0, 1.0, alpha]; 2]
+            })
+            .collect();
+        set_strip(mesh, &points, |i| RIBBON_WIDTH / 2.0 * fade(i));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    }
+}
+
+/// Chains go away with their player or when they have faded, so do their meshes.
+fn despawn_orphans(
+    mut commands: Commands,
+    visuals: Query<(Entity, &ChainVisual)>,
+    chains: Query<(), Or<(With<Chain>, With<LooseChain>)>>,
+) {
+    for (entity, visual) in visuals {
+        if !chains.contains(visual.chain) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Catmull-Rom spline through `points`, so the rope bends smoothly at every link.
+fn smooth(points: &[Vec2]) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let at = |i: isize| points[i.clamp(0, points.len() as isize - 1) as usize];
+    let mut smoothed = Vec::with_capacity((points.len() - 1) * SUBDIVISIONS + 1);
+    for i in 0..points.len() as isize - 1 {
+        let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+        for step in 0..SUBDIVISIONS {
+            let t = step as f32 / SUBDIVISIONS as f32;
+            let (t2, t3) = (t * t, t * t * t);
+            smoothed.push(
+                0.5 * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                    + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3),
+            );
+        }
+    }
+    smoothed.extend(points.last());
+    smoothed
+}
+
+/// Turns `mesh` into a strip along `points`, `half_width(i)` to either side of point `i`. Every
+/// point becomes two vertices, left then right.
+fn set_strip(mesh: &mut Mesh, points: &[Vec2], half_width: impl Fn(usize) -> f32) {
+    let mut positions = Vec::with_capacity(points.len() * 2);
+    for (i, point) in points.iter().enumerate() {
+        let previous = points[i.saturating_sub(1)];
+        let next = points[(i + 1).min(points.len() - 1)];
+        let normal = (next - previous).normalize_or_zero().perp() * half_width(i);
+        positions.extend([(point + normal).extend(0.0), (point - normal).extend(0.0)]);
+    }
+
+    let mut indices = Vec::with_capacity(points.len().saturating_sub(1) * 6);
+    for i in 0..points.len().saturating_sub(1) as u32 {
+        let (left, right) = (i * 2, i * 2 + 1);
+        let (next_left, next_right) = (left + 2, right + 2);
+        indices.extend([left, right, next_left, right, next_right, next_left]);
+    }
+
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_indices(Indices::U32(indices));
+}
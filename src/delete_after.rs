@@ -1,20 +1,186 @@
-use bevy::prelude::{Commands, Component, Entity, Query};
-use std::time::{Duration, Instant};
+use avian2d::prelude::*;
+use bevy::ecs::entity_disabling::Disabled;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::time::Duration;
 
-#[derive(Component)]
-pub struct DeleteAt(pub Instant);
+use crate::GameState;
+use crate::determinism::SimulationTick;
+use crate::net::NetSession;
+
+/// Which clock a [`DeleteAt`] counts down with. Both stop while the game is paused and follow its
+/// speed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Clock {
+    /// Once per frame, for things that are only for show.
+    #[default]
+    Virtual,
+    /// Once per fixed tick, for anything the simulation depends on, so it goes the same on every
+    /// run.
+    Fixed,
+}
+
+/// Despawns the entity once its time is up, along with its children and any joint attached to
+/// them.
+#[derive(Component, Clone, Debug)]
+pub struct DeleteAt {
+    remaining: Duration,
+    /// Fades the sprite out over this last part of the lifetime.
+    fade_out: Duration,
+    clock: Clock,
+    /// Alpha of the sprite before it started fading.
+    faded_from: Option<f32>,
+}
 
 impl DeleteAt {
     pub fn after(duration: Duration) -> Self {
-        DeleteAt(Instant::now() + duration)
+        Self {
+            remaining: duration,
+            fade_out: Duration::ZERO,
+            clock: Clock::Virtual,
+            faded_from: None,
+        }
+    }
+
+    pub fn fading_out(mut self, duration: Duration) -> Self {
+        self.fade_out = duration;
+        self
+    }
+
+    pub fn on_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    /// 1 until it starts fading out, down to 0 once its time is up.
+    pub fn opacity(&self) -> f32 {
+        if self.remaining < self.fade_out {
+            self.remaining.as_secs_f32() / self.fade_out.as_secs_f32()
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Despawned in this [`SimulationTick`] of an online match. Kept around [`Disabled`] as long as a
+/// rollback might bring it back, see [`net`](crate::net).
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Despawned(pub u32);
+
+/// Spawned by the simulation during a match, so a rollback to before it existed can despawn it
+/// again.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct SpawnedInMatch;
+
+/// Despawns entities along with their children and every joint attached to any of them.
+///
+/// In an online match they are only [`Despawned`], in case a rollback needs them back.
+#[derive(SystemParam)]
+pub struct Despawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    children: Query<'w, 's, &'static Children>,
+    revolute_joints: Query<'w, 's, (Entity, &'static RevoluteJoint)>,
+    distance_joints: Query<'w, 's, (Entity, &'static DistanceJoint)>,
+    despawned: Query<'w, 's, (), (With<Despawned>, With<Disabled>)>,
+    session: Option<Res<'w, NetSession>>,
+    state: Res<'w, State<GameState>>,
+    tick: Res<'w, SimulationTick>,
+}
+
+impl Despawner<'_, '_> {
+    pub fn despawn(&mut self, entity: Entity) {
+        self.despawn_all([entity]);
     }
+
+    /// Like [`despawn`](Self::despawn), for entities that might share joints.
+    pub fn despawn_all(&mut self, entities: impl IntoIterator<Item = Entity>) {
+        let entities: Vec<Entity> = entities.into_iter().collect();
+        // Children go with `despawn`, but joints are their own entities and would be left pointing
+        // at nothing.
+        let doomed: Vec<Entity> = entities
+            .iter()
+            .flat_map(|entity| {
+                std::iter::once(*entity).chain(self.children.iter_descendants(*entity))
+            })
+            .collect();
+        let keep = self.session.is_some() && *self.state.get() == GameState::Playing;
+        let joints = self
+            .revolute_joints
+            .iter()
+            .map(|(joint, revolute)| (joint, [revolute.entity1, revolute.entity2]))
+            .chain(
+                self.distance_joints
+                    .iter()
+                    .map(|(joint, distance)| (joint, [distance.entity1, distance.entity2])),
+            );
+        let joints: Vec<Entity> = joints
+            .filter(|(_, bodies)| bodies.iter().any(|body| doomed.contains(body)))
+            .map(|(joint, _)| joint)
+            .collect();
+
+        if keep {
+            // Already gone since an earlier tick, which is what a rollback needs to know.
+            let doomed = doomed
+                .into_iter()
+                .filter(|entity| !self.despawned.contains(*entity));
+            for entity in joints.into_iter().chain(doomed) {
+                self.commands
+                    .entity(entity)
+                    .try_insert((Disabled, Despawned(self.tick.0)));
+            }
+            return;
+        }
+        for joint in joints {
+            self.commands.entity(joint).despawn();
+        }
+        for entity in entities {
+            self.commands.entity(entity).try_despawn();
+        }
+    }
+}
+
+/// Counts down [`Clock::Virtual`] lifetimes, in `Update`.
+pub fn delete_at_virtual(
+    time: Res<Time>,
+    delete_at: Query<(Entity, &mut DeleteAt, Option<&mut Sprite>)>,
+    despawner: Despawner,
+) {
+    count_down(Clock::Virtual, &time, delete_at, despawner);
+}
+
+/// Counts down [`Clock::Fixed`] lifetimes, in `FixedUpdate`.
+pub fn delete_at_fixed(
+    time: Res<Time>,
+    delete_at: Query<(Entity, &mut DeleteAt, Option<&mut Sprite>)>,
+    despawner: Despawner,
+) {
+    count_down(Clock::Fixed, &time, delete_at, despawner);
 }
 
-pub fn delete_at(mut commands: Commands, delete_at: Query<(Entity, &DeleteAt)>) {
-    let now = Instant::now();
-    for (entity, DeleteAt(time)) in delete_at {
-        if &now >= time {
-            commands.entity(entity).despawn();
+fn count_down(
+    clock: Clock,
+    time: &Time,
+    delete_at: Query<(Entity, &mut DeleteAt, Option<&mut Sprite>)>,
+    mut despawner: Despawner,
+) {
+    for (entity, mut delete_at, sprite) in delete_at {
+        if delete_at.clock != clock {
+            continue;
+        }
+        delete_at.remaining = delete_at.remaining.saturating_sub(time.delta());
+
+        if let Some(mut sprite) = sprite
+            && delete_at.remaining < delete_at.fade_out
+        {
+            let alpha = *delete_at.faded_from.get_or_insert(sprite.color.alpha());
+            sprite.color.set_alpha(alpha * delete_at.opacity());
+        }
+        if delete_at.remaining.is_zero() {
+            despawner.despawn(entity);
         }
     }
 }
@@ -0,0 +1,221 @@
+//! Keeping the simulation the same every time it is run with the same input, and noticing when
+//! it isn't.
+//!
+//! Gameplay only advances in fixed ticks of [`TICK_RATE`], and only reads the clock of those.
+//! A change of [`MatchPhase`] made in a tick is applied at its end rather than once the frame is
+//! done, so the next tick already sees it however many run per frame. Everything else, like
+//! leaving the match, waits for the frame as usual.
+//! Build with `--features deterministic` to also have avian do its math the same way on every
+//! platform, which replays and online play between different machines rely on.
+//!
+//! After every tick a checksum of every body's position and velocity is taken. Start the game
+//! with `--checksums <file>` to write them down, one line per tick, and with
+//! `--compare-checksums <file>` to be told the first tick a run stops matching an earlier one:
+//!
+//! ```text
+//! chainwhips --record match.txt --checksums recorded.txt
+//! chainwhips --replay match.txt --compare-checksums recorded.txt
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+
+use crate::GameState;
+use crate::round::MatchPhase;
+
+/// Fixed ticks per second.
+pub const TICK_RATE: f64 = 64.0;
+
+/// Fixed ticks simulated since the match started.
+#[derive(Resource, Default)]
+pub struct SimulationTick(pub u32);
+
+/// Checksum of the state at the end of the last tick.
+#[derive(Resource, Default)]
+pub struct StateChecksum {
+    pub tick: u32,
+    pub value: u64,
+}
+
+#[derive(Resource)]
+struct ChecksumLog(BufWriter<File>);
+
+/// Checksums of an earlier run by tick. Ticks missing from the file aren't compared.
+#[derive(Resource)]
+struct ExpectedChecksums {
+    values: BTreeMap<u32, u64>,
+    diverged: bool,
+}
+
+impl ExpectedChecksums {
+    fn load(path: &Path) -> std::io::Result<Self> {
+        let mut values = BTreeMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let Some((tick, value)) = line.split_once(' ') else {
+                continue;
+            };
+            let (Ok(tick), Ok(value)) = (tick.parse(), u64::from_str_radix(value, 16)) else {
+                continue;
+            };
+            values.insert(tick, value);
+        }
+        Ok(Self {
+            values,
+            diverged: false,
+        })
+    }
+}
+
+pub struct DeterminismPlugin;
+
+impl Plugin for DeterminismPlugin {
+    fn build(&self, app: &mut App) {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--checksums", Some(path)) => match File::create(&path) {
+                    Ok(file) => {
+                        app.insert_resource(ChecksumLog(BufWriter::new(file)));
+                    }
+                    Err(e) => error!("Could not write checksums to {path}: {e}"),
+                },
+                ("--compare-checksums", Some(path)) => {
+                    match ExpectedChecksums::load(Path::new(&path)) {
+                        Ok(expected) => {
+                            app.insert_resource(expected);
+                        }
+                        Err(e) => error!("Could not read checksums from {path}: {e}"),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        app.insert_resource(Time::<Fixed>::from_hz(TICK_RATE))
+            .init_resource::<SimulationTick>()
+            .init_resource::<StateChecksum>()
+            .add_systems(OnEnter(GameState::Playing), reset_tick)
+            .add_systems(
+                FixedLast,
+                (
+                    take_checksum,
+                    log_checksum.run_if(resource_exists::<ChecksumLog>),
+                    compare_checksum.run_if(resource_exists::<ExpectedChecksums>),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedLast,
+                apply_phase_transition
+                    .after(log_checksum)
+                    .after(compare_checksum),
+            );
+    }
+}
+
+/// Does what the `StateTransition` schedule would for [`MatchPhase`] alone. Sends no
+/// `StateTransitionEvent`, which would have that schedule run `OnEnter` again at the end of the
+/// frame.
+fn apply_phase_transition(world: &mut World) {
+    let Some(exited) = world
+        .get_resource::<State<MatchPhase>>()
+        .map(|phase| *phase.get())
+    else {
+        return;
+    };
+    let Some(NextState::Pending(entered)) = world
+        .get_resource_mut::<NextState<MatchPhase>>()
+        .map(|mut next| std::mem::take(&mut *next))
+    else {
+        return;
+    };
+    if entered == exited {
+        return;
+    }
+
+    world.insert_resource(State::new(entered));
+    let _ = world.try_run_schedule(OnExit(exited));
+    let _ = world.try_run_schedule(OnTransition { exited, entered });
+    let _ = world.try_run_schedule(OnEnter(entered));
+}
+
+fn reset_tick(mut tick: ResMut<SimulationTick>) {
+    tick.0 = 0;
+}
+
+pub fn take_checksum(
+    mut tick: ResMut<SimulationTick>,
+    mut checksum: ResMut<StateChecksum>,
+    bodies: Query<(
+        &RigidBody,
+        &Position,
+        &Rotation,
+        &LinearVelocity,
+        &AngularVelocity,
+    )>,
+) {
+    checksum.tick = tick.0;
+    checksum.value = bodies
+        .iter()
+        .filter(|(body, ..)| !body.is_static())
+        .map(|(_, position, rotation, linear, angular)| {
+            hash([
+                position.x,
+                position.y,
+                rotation.as_radians(),
+                linear.x,
+                linear.y,
+                angular.0,
+            ])
+        })
+        // Adding up doesn't care which order the query visits the bodies in.
+        .fold(0, u64::wrapping_add);
+    tick.0 += 1;
+}
+
+/// FNV-1a over the exact bits, so even the smallest difference shows.
+fn hash(values: [f32; 6]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in values
+        .iter()
+        .flat_map(|value| value.to_bits().to_le_bytes())
+    {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn log_checksum(
+    mut commands: Commands,
+    checksum: Res<StateChecksum>,
+    mut log: ResMut<ChecksumLog>,
+) {
+    if let Err(e) = writeln!(log.0, "{} {:016x}", checksum.tick, checksum.value) {
+        error!("Stopped writing checksums: {e}");
+        commands.remove_resource::<ChecksumLog>();
+    }
+}
+
+fn compare_checksum(checksum: Res<StateChecksum>, mut expected: ResMut<ExpectedChecksums>) {
+    if expected.diverged {
+        return;
+    }
+    let Some(value) = expected.values.get(&checksum.tick) else {
+        return;
+    };
+    if *value != checksum.value {
+        error!(
+            "Simulation diverged at tick {}: expected {value:016x}, got {:016x}",
+            checksum.tick, checksum.value
+        );
+        expected.diverged = true;
+    }
+}
@@ -0,0 +1,234 @@
+//! Grabbing things with the tip of the chain and throwing them.
+//!
+//! Holding grapple while the tip touches a dynamic body, like the duck or another player, latches
+//! it on. While it is held it weighs about as much as a heavy tip, so it can be swung around.
+//! Letting go throws it with the speed of the tip, and the first thing it hits afterwards is a
+//! [`ImpactKind::Throw`](crate::impact::ImpactKind::Throw) impact by the thrower.
+
+use std::time::Duration;
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+
+use crate::delete_after::{Despawner, SpawnedInMatch};
+use crate::grapple::{Grapple, GrappleSystems};
+use crate::input::InputFrame;
+use crate::round::{MatchPhase, Respawned, fighting};
+use crate::status::Stunned;
+use crate::{Chain, ChainLink, GameState, LINK_MASS, Player};
+
+/// How much heavier than a link something held is.
+const HELD_MASS: f32 = 20.0;
+/// How long a thrown body can fly before hitting something stops counting.
+const THROW_WINDOW: Duration = Duration::from_secs(2);
+
+/// The player holds `object` at the tip of their chain.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Grab {
+    pub object: Entity,
+    /// Joint between the tip and `object`, also in [`Held`].
+    joint: Entity,
+}
+
+/// Held by the player `by`.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Held {
+    pub by: Entity,
+    /// The link holding on, the tip at the time.
+    link: Entity,
+    /// Joint between `link` and the held body.
+    joint: Entity,
+    /// Its own [`Mass`] from before it was grabbed, if it had one.
+    mass: Option<f32>,
+    /// Whether it had [`CollisionEventsEnabled`] of its own before it was grabbed.
+    collision_events: bool,
+}
+
+/// Thrown by the player `by` and still flying.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Thrown {
+    pub by: Entity,
+    remaining: Duration,
+    /// Whether it had [`CollisionEventsEnabled`] before, so it is only taken away if the throw
+    /// added it.
+    collision_events: bool,
+}
+
+impl Thrown {
+    /// Makes the body just a body again.
+    pub fn land(&self, entity: &mut EntityCommands) {
+        entity.remove::<Thrown>();
+        if !self.collision_events {
+            entity.remove::<CollisionEventsEnabled>();
+        }
+    }
+}
+
+pub struct GrabPlugin;
+
+impl Plugin for GrabPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                // Also once the fight is over, so nothing is left dangling.
+                let_go,
+                (grab_on, land_throws).run_if(fighting),
+            )
+                .chain()
+                // A tip can either hook onto a tile or hold something, tiles go first.
+                .after(GrappleSystems)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// Lets go of held bodies, throwing them if the player let go of grapple. Stunned or respawned
+/// players, ones whose tip was cut off, or everyone once the fight is over, just drop them.
+fn let_go(
+    mut commands: Commands,
+    mut despawner: Despawner,
+    mut respawned: EventReader<Respawned>,
+    phase: Option<Res<State<MatchPhase>>>,
+    held: Query<(Entity, &Held)>,
+    holders: Query<(Entity, &Grab, &InputFrame, &Chain, Has<Stunned>)>,
+    velocities: Query<&LinearVelocity>,
+) {
+    let respawned: Vec<Entity> = respawned.read().map(|Respawned(player)| *player).collect();
+    let fighting = fighting(phase);
+    for (object, held) in &held {
+        let holder = holders
+            .get(held.by)
+            .ok()
+            .filter(|(player, grab, _, chain, _)| {
+                grab.object == object
+                    && chain.links.last() == Some(&held.link)
+                    && !respawned.contains(player)
+                    && !respawned.contains(&object)
+            });
+        let throwing = match holder {
+            Some((_, _, frame, _, stunned)) if fighting && frame.grapple && !stunned => continue,
+            Some((_, _, _, _, stunned)) => fighting && !stunned,
+            None => false,
+        };
+
+        despawner.despawn(held.joint);
+        let mut entity = commands.entity(object);
+        entity.remove::<Held>();
+        match held.mass {
+            Some(mass) => entity.insert(Mass(mass)),
+            None => entity.remove::<Mass>(),
+        };
+        if throwing {
+            let velocity = velocities.get(held.link).copied().unwrap_or_default();
+            entity.insert((
+                velocity,
+                Thrown {
+                    by: held.by,
+                    remaining: THROW_WINDOW,
+                    collision_events: held.collision_events,
+                },
+                // Players don't report collisions otherwise.
+                CollisionEventsEnabled,
+            ));
+        }
+        if let Ok(mut holder) = commands.get_entity(held.by) {
+            holder.remove::<Grab>();
+        }
+    }
+
+    // Whatever they held is gone, without taking the joint with it.
+    for (player, grab, ..) in &holders {
+        if !held.contains(grab.object) {
+            despawner.despawn(grab.joint);
+            commands.entity(player).remove::<Grab>();
+        }
+    }
+}
+
+/// Latches the closest dynamic body the tip touches onto the tip of every chain held out for
+/// grabbing.
+fn grab_on(
+    mut commands: Commands,
+    players: Query<
+        (Entity, &InputFrame, &Chain, Has<Stunned>),
+        (With<Player>, Without<Grab>, Without<Grapple>),
+    >,
+    tips: Query<(&Transform, &CollidingEntities), With<ChainLink>>,
+    bodies: Query<
+        (
+            &RigidBody,
+            &Transform,
+            Option<&Mass>,
+            Has<CollisionEventsEnabled>,
+            Option<&Thrown>,
+        ),
+        (Without<ChainLink>, Without<Sensor>, Without<Held>),
+    >,
+) {
+    // Two players can't grab the same thing in the same tick either.
+    let mut taken = Vec::new();
+    for (player, frame, chain, stunned) in players {
+        if !frame.grapple || stunned {
+            continue;
+        }
+        let Some(link) = chain.links.last() else {
+            continue;
+        };
+        let Ok((tip, touching)) = tips.get(*link) else {
+            continue;
+        };
+        let tip = tip.translation.xy();
+        let Some((object, transform, mass, collision_events)) = touching
+            .iter()
+            .filter(|entity| **entity != player && !taken.contains(*entity))
+            .filter_map(|entity| {
+                let (body, transform, mass, collision_events, thrown) = bodies.get(*entity).ok()?;
+                // Caught mid-throw, what the throw added doesn't count.
+                let collision_events =
+                    thrown.map_or(collision_events, |thrown| thrown.collision_events);
+                body.is_dynamic()
+                    .then_some((*entity, transform, mass, collision_events))
+            })
+            .min_by(|(_, a, ..), (_, b, ..)| {
+                let distance = |transform: &Transform| transform.translation.xy().distance(tip);
+                distance(a).total_cmp(&distance(b))
+            })
+        else {
+            continue;
+        };
+
+        // Held by where the tip touched it, in its own frame.
+        let anchor = transform.rotation.inverse() * (tip - transform.translation.xy()).extend(0.0);
+        // A child of the player, so it goes with them.
+        let joint = commands
+            .spawn((
+                RevoluteJoint::new(*link, object).with_local_anchor_2(anchor.xy()),
+                ChildOf(player),
+                SpawnedInMatch,
+            ))
+            .id();
+        commands.entity(object).insert((
+            Held {
+                by: player,
+                link: *link,
+                joint,
+                mass: mass.map(|mass| mass.0),
+                collision_events,
+            },
+            Mass(LINK_MASS * HELD_MASS),
+        ));
+        commands.entity(player).insert(Grab { object, joint });
+        taken.push(object);
+    }
+}
+
+/// Thrown bodies that flew for long enough without hitting anything are just bodies again.
+fn land_throws(mut commands: Commands, time: Res<Time>, thrown: Query<(Entity, &mut Thrown)>) {
+    for (entity, mut thrown) in thrown {
+        thrown.remaining = thrown.remaining.saturating_sub(time.delta());
+        if thrown.remaining.is_zero() {
+            thrown.land(&mut commands.entity(entity));
+        }
+    }
+}
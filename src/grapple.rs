@@ -0,0 +1,195 @@
+//! Hooking the tip of the chain onto terrain to swing from it.
+//!
+//! Tiles get a bool property `grapple` in the tileset in Tiled to be [`Grappleable`]. While a
+//! player holds grapple and the tip of their chain touches one of those, the tip is pinned to the
+//! tile and a rope from the hook to the player keeps them from getting any further away, so they
+//! swing around it. Jump reels the rope in, slam lets it out, up to as far as the chain reaches.
+//! Letting go of grapple lets go of the tile.
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::prelude::TnuaUserControlsSystemSet;
+
+use crate::delete_after::{Despawner, SpawnedInMatch};
+use crate::grab::Grab;
+use crate::input::InputFrame;
+use crate::round::{MatchPhase, Respawned, RoundSystems, fighting};
+use crate::status::Stunned;
+use crate::{Chain, GameState, Player};
+
+/// Pixels a second the rope is reeled in or out.
+const REEL_SPEED: f32 = 400.0;
+/// The rope never gets shorter than this, or players would be pulled into the tile.
+const MIN_ROPE: f32 = 40.0;
+/// Distance between two links along the chain.
+const LINK_LENGTH: f32 = 20.0;
+
+/// Hooking on, reeling and letting go, after [`RoundSystems`].
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct GrappleSystems;
+
+/// A tile the chain can hook onto.
+#[derive(Component)]
+pub struct Grappleable;
+
+/// The player is hanging from a tile.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Grapple {
+    tile: Entity,
+    /// Where on `tile` the tip hooked on, relative to its center.
+    point: Vec2,
+    /// The link that hooked on, the tip at the time.
+    link: Entity,
+    /// Joint pinning `link` to `tile`.
+    hook: Entity,
+    /// Joint keeping the player within `length` of the hook.
+    rope: Entity,
+    pub length: f32,
+}
+
+pub struct GrapplePlugin;
+
+impl Plugin for GrapplePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                // Also once the fight is over, so nobody is left hanging.
+                let_go,
+                (hook_on, reel).run_if(fighting),
+            )
+                .chain()
+                .in_set(GrappleSystems)
+                // After everything else that moves players around this tick, like respawning.
+                .after(RoundSystems)
+                .after(TnuaUserControlsSystemSet)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+fn rope(tile: Entity, point: Vec2, player: Entity, length: f32) -> DistanceJoint {
+    DistanceJoint::new(tile, player)
+        .with_local_anchor_1(point)
+        .with_limits(0.0, length)
+}
+
+/// How long the rope of `chain` can get.
+fn reach(chain: &Chain) -> f32 {
+    (chain.links.len() + 1) as f32 * LINK_LENGTH
+}
+
+/// Lets go when grapple is released, the player is stunned or respawned, whatever was hooked is
+/// gone or the fight is over. The tip being cut off or the chain changing length counts too.
+fn let_go(
+    mut commands: Commands,
+    mut despawner: Despawner,
+    mut respawned: EventReader<Respawned>,
+    phase: Option<Res<State<MatchPhase>>>,
+    players: Query<(Entity, &Grapple, &InputFrame, &Chain, Has<Stunned>)>,
+    tiles: Query<(), With<Grappleable>>,
+) {
+    let respawned: Vec<Entity> = respawned.read().map(|Respawned(player)| *player).collect();
+    let fighting = fighting(phase);
+    for (player, grapple, frame, chain, stunned) in players {
+        let holding = fighting
+            && frame.grapple
+            && !stunned
+            && !respawned.contains(&player)
+            && tiles.contains(grapple.tile)
+            && chain.links.last() == Some(&grapple.link);
+        if holding {
+            continue;
+        }
+
+        // The tip may have taken the hook with it already.
+        despawner.despawn_all([grapple.hook, grapple.rope]);
+        commands.entity(player).remove::<Grapple>();
+    }
+}
+
+/// Hooks the tip of every chain held out for grappling onto the closest [`Grappleable`] tile it
+/// touches.
+fn hook_on(
+    mut commands: Commands,
+    players: Query<
+        (Entity, &InputFrame, &Chain, &Transform, Has<Stunned>),
+        (With<Player>, Without<Grapple>, Without<Grab>),
+    >,
+    tips: Query<(&Transform, &CollidingEntities)>,
+    tiles: Query<&Transform, With<Grappleable>>,
+) {
+    for (player, frame, chain, transform, stunned) in players {
+        if !frame.grapple || stunned {
+            continue;
+        }
+        let Some(link) = chain.links.last() else {
+            continue;
+        };
+        let Ok((tip, touching)) = tips.get(*link) else {
+            continue;
+        };
+        let tip = tip.translation.xy();
+        let Some((tile, tile_position)) = touching
+            .iter()
+            .filter_map(|entity| Some((*entity, tiles.get(*entity).ok()?.translation.xy())))
+            .min_by(|(_, a), (_, b)| a.distance(tip).total_cmp(&b.distance(tip)))
+        else {
+            continue;
+        };
+
+        let point = tip - tile_position;
+        let length = transform
+            .translation
+            .xy()
+            .distance(tip)
+            .clamp(MIN_ROPE, reach(chain));
+        // Children of the player, so they go with it.
+        let hook = commands
+            .spawn((
+                RevoluteJoint::new(tile, *link).with_local_anchor_1(point),
+                ChildOf(player),
+                SpawnedInMatch,
+            ))
+            .id();
+        let rope = commands
+            .spawn((
+                rope(tile, point, player, length),
+                ChildOf(player),
+                SpawnedInMatch,
+            ))
+            .id();
+        commands.entity(player).insert(Grapple {
+            tile,
+            point,
+            link: *link,
+            hook,
+            rope,
+            length,
+        });
+    }
+}
+
+/// Shortens the rope while jump is held, and lets it out while slam is.
+fn reel(
+    mut commands: Commands,
+    time: Res<Time>,
+    players: Query<(Entity, &mut Grapple, &InputFrame, &Chain)>,
+) {
+    for (player, mut grapple, frame, chain) in players {
+        let direction = frame.slam as i8 - frame.jump as i8;
+        if direction == 0 {
+            continue;
+        }
+
+        let length = (grapple.length + direction as f32 * REEL_SPEED * time.delta_secs())
+            .clamp(MIN_ROPE, reach(chain));
+        if length == grapple.length {
+            continue;
+        }
+        grapple.length = length;
+        commands
+            .entity(grapple.rope)
+            .insert(rope(grapple.tile, grapple.point, player, length));
+    }
+}
@@ -0,0 +1,103 @@
+//! Things on the map that are bad for chains.
+//!
+//! Blades are point objects of class `blade` on an object layer in Tiled. Any chain swept through
+//! one is cut where it touched, and the player is left with what is still attached until they are
+//! knocked out.
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::TnuaNotPlatform;
+use bevy_tnua::prelude::TnuaUserControlsSystemSet;
+
+use crate::chain::ChainEditor;
+use crate::round::fighting;
+use crate::tilemap::helpers::tiled::TiledMap;
+use crate::tilemap::objects_of_class;
+use crate::{ChainLink, GameState};
+
+const BLADE_RADIUS: f32 = 12.0;
+/// Radians per second, only for show.
+const BLADE_SPIN: f32 = 8.0;
+
+#[derive(Component)]
+pub struct Blade;
+
+pub struct HazardPlugin;
+
+impl Plugin for HazardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (spawn_blades, spin_blades))
+            .add_systems(
+                FixedUpdate,
+                cut_chains
+                    .before(TnuaUserControlsSystemSet)
+                    .run_if(in_state(GameState::Playing).and(fighting)),
+            );
+    }
+}
+
+/// Replaces every blade with the ones in the map whenever it is (re)loaded.
+fn spawn_blades(
+    mut commands: Commands,
+    mut map_events: EventReader<AssetEvent<TiledMap>>,
+    maps: Res<Assets<TiledMap>>,
+    blades: Query<Entity, With<Blade>>,
+) {
+    for event in map_events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        let Some(map) = maps.get(*id).map(|map| &map.map) else {
+            continue;
+        };
+        for blade in &blades {
+            commands.entity(blade).despawn();
+        }
+
+        for (_, position) in objects_of_class(map, "blade") {
+            commands.spawn((
+                Blade,
+                Transform::from_translation(position.extend(2.0)),
+                Sprite {
+                    color: Color::srgb(0.8, 0.82, 0.85),
+                    custom_size: Some(Vec2::splat(BLADE_RADIUS * 1.6)),
+                    ..default()
+                },
+                RigidBody::Static,
+                Collider::circle(BLADE_RADIUS),
+                Sensor,
+                CollidingEntities::default(),
+                TnuaNotPlatform,
+            ));
+        }
+    }
+}
+
+fn spin_blades(time: Res<Time>, blades: Query<&mut Transform, With<Blade>>) {
+    for mut transform in blades {
+        transform.rotate_z(BLADE_SPIN * time.delta_secs());
+    }
+}
+
+/// Cuts every chain touching a blade at the touching link closest to its player.
+fn cut_chains(
+    blades: Query<&CollidingEntities, With<Blade>>,
+    links: Query<&ChainLink>,
+    mut chains: ChainEditor,
+) {
+    let mut touching: Vec<(Entity, Entity, usize)> = blades
+        .iter()
+        .flat_map(|colliding| colliding.iter())
+        .filter_map(|entity| {
+            let ChainLink { player } = links.get(*entity).ok()?;
+            Some((*player, *entity, chains.link_index(*entity)?))
+        })
+        .collect();
+    // Closest first, cutting there takes the rest of the chain with it.
+    touching.sort_by_key(|(player, _, index)| (*player, *index));
+    touching.dedup_by_key(|(player, ..)| *player);
+
+    for (_, link, _) in touching {
+        chains.sever(link);
+    }
+}
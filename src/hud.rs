@@ -0,0 +1,354 @@
+//! What is drawn over the world during a match: a card per player with their health, stocks,
+//! score and whatever pickups and effects they have going, the round timer, banners for the
+//! countdown and the end of the match, and arrows at the edge of the screen pointing at players
+//! no camera can see.
+
+use bevy::prelude::*;
+
+use crate::player::{Appearance, PlayerNumber};
+use crate::round::{Health, MatchPhase, RoundTimer, Score, Stocks};
+use crate::status::{ActiveEffects, EffectsQuery};
+use crate::{CHAIN_LINK_COUNT, Chain, GameState, Player};
+
+const HEALTH_BAR_WIDTH: f32 = 120.0;
+/// How far from the edge of the screen indicators are kept, in logical pixels.
+const INDICATOR_MARGIN: f32 = 24.0;
+const INDICATOR_SIZE: f32 = 36.0;
+/// How long "Fight!" stays up once the countdown is over, in seconds.
+const FIGHT_BANNER_TIME: f32 = 1.0;
+
+#[derive(Component)]
+struct PlayerCards;
+
+/// Shows the state of `player`. The parts of it are marked with the same entity.
+#[derive(Component)]
+struct PlayerCard(Entity);
+
+#[derive(Component)]
+struct HealthFill(Entity);
+
+#[derive(Component)]
+struct StocksText(Entity);
+
+#[derive(Component)]
+struct ScoreText(Entity);
+
+/// Effects on the player with the time they have left, and links their chain gained.
+#[derive(Component)]
+struct EffectsText(Entity);
+
+#[derive(Component)]
+struct RoundClock;
+
+#[derive(Component)]
+struct Banner;
+
+/// Points at `player` from the edge of the screen while they are out of view.
+#[derive(Component)]
+struct OffscreenIndicator(Entity);
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Playing), spawn_hud)
+            .add_systems(
+                Update,
+                (
+                    sync_player_cards,
+                    update_health_bars,
+                    update_stocks,
+                    update_scores,
+                    update_effects,
+                    update_clock,
+                    update_banner,
+                    update_offscreen_indicators,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+fn spawn_hud(mut commands: Commands) {
+    commands
+        .spawn((
+            StateScoped(GameState::Playing),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                padding: UiRect::all(Val::Px(12.0)),
+                ..default()
+            },
+            // Below the touch controls.
+            GlobalZIndex(5),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                PlayerCards,
+                Node {
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::SpaceBetween,
+                    ..default()
+                },
+            ));
+            parent.spawn((RoundClock, Text::default(), TextFont::from_font_size(32.0)));
+            parent.spawn((
+                Banner,
+                Text::default(),
+                TextFont::from_font_size(96.0),
+                TextLayout::new_with_justify(JustifyText::Center),
+                Node {
+                    margin: UiRect::top(Val::Percent(15.0)),
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Adds a card and an indicator for every new player, and drops them for players who left.
+fn sync_player_cards(
+    mut commands: Commands,
+    cards_root: Single<Entity, With<PlayerCards>>,
+    players: Query<(Entity, &PlayerNumber, &Appearance), Added<Player>>,
+    cards: Query<(Entity, &PlayerCard)>,
+    indicators: Query<(Entity, &OffscreenIndicator)>,
+    alive: Query<(), With<Player>>,
+) {
+    for (entity, PlayerCard(player)) in &cards {
+        if !alive.contains(*player) {
+            commands.entity(entity).despawn();
+        }
+    }
+    for (entity, OffscreenIndicator(player)) in &indicators {
+        if !alive.contains(*player) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for (player, number, appearance) in players {
+        let color = appearance.color();
+        commands.entity(*cards_root).with_children(|parent| {
+            parent
+                .spawn((
+                    PlayerCard(player),
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(8.0)),
+                        row_gap: Val::Px(4.0),
+                        border: UiRect::all(Val::Px(3.0)),
+                        ..default()
+                    },
+                    BorderColor(color),
+                    BackgroundColor(Color::BLACK.with_alpha(0.5)),
+                ))
+                .with_children(|card| {
+                    card.spawn((Text::new(format!("P{}", number.0 + 1)), TextColor(color)));
+                    card.spawn((
+                        Node {
+                            width: Val::Px(HEALTH_BAR_WIDTH),
+                            height: Val::Px(10.0),
+                            ..default()
+                        },
+                        BackgroundColor(Color::srgb(0.2, 0.2, 0.2)),
+                    ))
+                    .with_child((
+                        HealthFill(player),
+                        Node {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        BackgroundColor(color),
+                    ));
+                    card.spawn((StocksText(player), Text::default()));
+                    card.spawn((ScoreText(player), Text::default()));
+                    card.spawn((
+                        EffectsText(player),
+                        Text::default(),
+                        TextFont::from_font_size(14.0),
+                    ));
+                });
+        });
+
+        commands.spawn((
+            StateScoped(GameState::Playing),
+            OffscreenIndicator(player),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Px(INDICATOR_SIZE),
+                height: Val::Px(INDICATOR_SIZE),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                border: UiRect::all(Val::Px(3.0)),
+                ..default()
+            },
+            BorderColor(color),
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+            BorderRadius::MAX,
+            GlobalZIndex(5),
+            Visibility::Hidden,
+            children![(
+                Text::new(format!("P{}", number.0 + 1)),
+                TextFont::from_font_size(14.0),
+                TextColor(color),
+            )],
+        ));
+    }
+}
+
+fn update_health_bars(
+    players: Query<&Health, Changed<Health>>,
+    bars: Query<(&HealthFill, &mut Node)>,
+) {
+    for (HealthFill(player), mut node) in bars {
+        if let Ok(health) = players.get(*player) {
+            node.width = Val::Percent(health.fraction().clamp(0.0, 1.0) * 100.0);
+        }
+    }
+}
+
+fn update_stocks(players: Query<&Stocks, Changed<Stocks>>, texts: Query<(&StocksText, &mut Text)>) {
+    for (StocksText(player), mut text) in texts {
+        if let Ok(stocks) = players.get(*player) {
+            text.0 = format!("Stocks: {}", stocks.0);
+        }
+    }
+}
+
+fn update_scores(players: Query<&Score, Changed<Score>>, texts: Query<(&ScoreText, &mut Text)>) {
+    for (ScoreText(player), mut text) in texts {
+        if let Ok(score) = players.get(*player) {
+            text.0 = format!("K.O.s: {}", score.0);
+        }
+    }
+}
+
+fn update_effects(
+    players: Query<(&Chain, EffectsQuery), With<Player>>,
+    texts: Query<(&EffectsText, &mut Text)>,
+) {
+    for (EffectsText(player), mut text) in texts {
+        let Ok((chain, effects)) = players.get(*player) else {
+            continue;
+        };
+        let mut lines: Vec<String> = ActiveEffects::new(effects)
+            .iter()
+            .map(|(kind, timer)| {
+                format!(
+                    "{} {}s",
+                    kind.name(),
+                    timer.remaining().as_secs_f32().ceil()
+                )
+            })
+            .collect();
+        let extra = chain.links.len().saturating_sub(CHAIN_LINK_COUNT);
+        if extra > 0 {
+            lines.push(format!("+{extra} links"));
+        }
+        let effects = lines.join("\n");
+        // Only touch it when it changed, or the layout is redone every frame.
+        if text.0 != effects {
+            text.0 = effects;
+        }
+    }
+}
+
+fn update_clock(
+    timer: Res<RoundTimer>,
+    phase: Res<State<MatchPhase>>,
+    mut clock: Single<(&mut Text, &mut TextColor), With<RoundClock>>,
+) {
+    let (text, color) = &mut *clock;
+    match phase.get() {
+        MatchPhase::Fight => {
+            let seconds = timer.remaining().unwrap_or_default().as_secs_f32().ceil() as u32;
+            text.0 = format!("{}:{:02}", seconds / 60, seconds % 60);
+            color.0 = if seconds <= 10 {
+                Color::srgb(1.0, 0.4, 0.3)
+            } else {
+                Color::WHITE
+            };
+        }
+        MatchPhase::SuddenDeath => {
+            text.0 = "0:00".to_string();
+            color.0 = Color::srgb(1.0, 0.2, 0.2);
+        }
+        MatchPhase::Countdown | MatchPhase::Finished => text.0.clear(),
+    }
+}
+
+fn update_banner(
+    timer: Res<RoundTimer>,
+    phase: Res<State<MatchPhase>>,
+    mut banner: Single<&mut Text, With<Banner>>,
+    players: Query<(&Stocks, &PlayerNumber), With<Player>>,
+) {
+    banner.0 = match phase.get() {
+        MatchPhase::Countdown => {
+            let seconds = timer.remaining().unwrap_or_default().as_secs_f32().ceil() as u32;
+            format!("{seconds}")
+        }
+        MatchPhase::Fight if timer.elapsed.as_secs_f32() < FIGHT_BANNER_TIME => {
+            "Fight!".to_string()
+        }
+        MatchPhase::Fight => String::new(),
+        MatchPhase::SuddenDeath if timer.elapsed.as_secs_f32() < FIGHT_BANNER_TIME => {
+            "Sudden death!".to_string()
+        }
+        MatchPhase::SuddenDeath => String::new(),
+        MatchPhase::Finished => match players.iter().find(|(stocks, _)| stocks.0 > 0) {
+            Some((_, number)) => format!("P{} wins!", number.0 + 1),
+            None => "Nobody wins".to_string(),
+        },
+    };
+}
+
+/// Shows an indicator for every player outside the view of all cameras, at the edge of the
+/// screen in the direction they are in.
+fn update_offscreen_indicators(
+    cameras: Query<(&Camera, &GlobalTransform), (With<Camera2d>, Without<IsDefaultUiCamera>)>,
+    players: Query<&GlobalTransform, With<Player>>,
+    indicators: Query<(&OffscreenIndicator, &mut Node, &mut Visibility)>,
+) {
+    let active: Vec<_> = cameras
+        .iter()
+        .filter(|(camera, _)| camera.is_active)
+        .collect();
+
+    for (OffscreenIndicator(player), mut node, mut visibility) in indicators {
+        let Ok(player) = players.get(*player) else {
+            continue;
+        };
+        let position = player.translation();
+
+        let seen = active.iter().any(|(camera, transform)| {
+            let (Some(rect), Ok(point)) = (
+                camera.logical_viewport_rect(),
+                camera.world_to_viewport(transform, position),
+            ) else {
+                return false;
+            };
+            rect.contains(point)
+        });
+        // Point from the first camera, while split every player has their own anyway.
+        let edge = active.first().and_then(|(camera, transform)| {
+            let rect = camera.logical_viewport_rect()?;
+            let point = camera.world_to_viewport(transform, position).ok()?;
+            let inner = rect.inflate(-INDICATOR_MARGIN - INDICATOR_SIZE / 2.0);
+            // A tiny viewport has no inside, keep to its middle then.
+            Some(point.clamp(inner.min, inner.max.max(inner.min)))
+        });
+
+        match edge {
+            Some(edge) if !seen => {
+                node.left = Val::Px(edge.x - INDICATOR_SIZE / 2.0);
+                node.top = Val::Px(edge.y - INDICATOR_SIZE / 2.0);
+                *visibility = Visibility::Inherited;
+            }
+            _ => *visibility = Visibility::Hidden,
+        }
+    }
+}
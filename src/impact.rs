@@ -0,0 +1,187 @@
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::TnuaAction;
+use bevy_tnua::builtins::TnuaBuiltinDash;
+use bevy_tnua::prelude::*;
+
+use crate::grab::Thrown;
+use crate::net::{NetSession, resimulating};
+use crate::{ChainLink, GameState, Player};
+
+/// The whip tip has to move at least this fast, relative to its player, for a hit to count.
+const MIN_WHIP_IMPACT: f32 = 400.0;
+/// And anything thrown this fast.
+const MIN_THROW_IMPACT: f32 = 300.0;
+
+/// Something hit something else hard enough that the game should react to it.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ImpactEvent {
+    pub position: Vec2,
+    /// Speed of the impact, in pixels per second.
+    pub strength: f32,
+    pub kind: ImpactKind,
+    /// The player who caused it.
+    pub source: Entity,
+    /// What was hit, if it was a body rather than the ground.
+    pub target: Option<Entity>,
+    /// Happened in a tick simulated again after a [rollback](crate::net). Anything only for show
+    /// skips these, it most likely showed them the first time round.
+    pub resimulated: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImpactKind {
+    /// A chain link hit something other than its own player.
+    Whip,
+    /// A player landed out of a slam.
+    Slam,
+    /// Something a player threw hit something, see [`grab`](crate::grab).
+    Throw,
+}
+
+/// The player is slamming down, `speed` is the fastest they fell so far.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Slamming {
+    speed: f32,
+}
+
+/// Everything sending [`ImpactEvent`]s, for systems that want to react in the same tick.
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ImpactSystems;
+
+pub struct ImpactPlugin;
+
+impl Plugin for ImpactPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ImpactEvent>().add_systems(
+            FixedUpdate,
+            (whip_impacts, slam_impacts, throw_impacts)
+                .in_set(ImpactSystems)
+                // Sees what the controller made of this tick's input.
+                .after(TnuaPipelineStages::Logic)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+fn whip_impacts(
+    mut collisions: EventReader<CollisionStarted>,
+    mut impacts: EventWriter<ImpactEvent>,
+    links: Query<(&ChainLink, &LinearVelocity, &Transform)>,
+    players: Query<&LinearVelocity, With<Player>>,
+    other_links: Query<&ChainLink>,
+    sensors: Query<(), With<Sensor>>,
+    session: Option<Res<NetSession>>,
+) {
+    let resimulated = resimulating(session);
+    for CollisionStarted(a, b) in collisions.read() {
+        for (link, other) in [(a, b), (b, a)] {
+            let Ok((ChainLink { player }, velocity, transform)) = links.get(*link) else {
+                continue;
+            };
+            // The chain constantly brushes against itself and its player, and sweeps through
+            // pickups without hitting them.
+            let own_chain = other_links
+                .get(*other)
+                .is_ok_and(|other| other.player == *player);
+            if *other == *player || own_chain || sensors.contains(*other) {
+                continue;
+            }
+            let Ok(player_velocity) = players.get(*player) else {
+                continue;
+            };
+
+            let strength = (velocity.0 - player_velocity.0).length();
+            if strength >= MIN_WHIP_IMPACT {
+                impacts.write(ImpactEvent {
+                    position: transform.translation.xy(),
+                    strength,
+                    kind: ImpactKind::Whip,
+                    source: *player,
+                    target: Some(*other),
+                    resimulated,
+                });
+            }
+        }
+    }
+}
+
+fn slam_impacts(
+    mut commands: Commands,
+    mut impacts: EventWriter<ImpactEvent>,
+    players: Query<
+        (
+            Entity,
+            &TnuaController,
+            &LinearVelocity,
+            &Transform,
+            Option<&mut Slamming>,
+        ),
+        With<Player>,
+    >,
+    session: Option<Res<NetSession>>,
+) {
+    let resimulated = resimulating(session);
+    for (entity, controller, velocity, transform, slamming) in players {
+        let airborne = controller.is_airborne().unwrap_or(false);
+        match slamming {
+            Some(mut slamming) if airborne => {
+                slamming.speed = slamming.speed.max(-velocity.y);
+            }
+            Some(slamming) => {
+                impacts.write(ImpactEvent {
+                    position: transform.translation.xy(),
+                    strength: slamming.speed,
+                    kind: ImpactKind::Slam,
+                    source: entity,
+                    target: None,
+                    resimulated,
+                });
+                commands.entity(entity).remove::<Slamming>();
+            }
+            None if airborne && controller.action_name() == Some(TnuaBuiltinDash::NAME) => {
+                commands
+                    .entity(entity)
+                    .insert(Slamming { speed: -velocity.y });
+            }
+            None => {}
+        }
+    }
+}
+
+/// The first thing a thrown body hits, other than its thrower, ends the throw.
+fn throw_impacts(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionStarted>,
+    mut impacts: EventWriter<ImpactEvent>,
+    thrown: Query<(&Thrown, &LinearVelocity, &Transform)>,
+    links: Query<&ChainLink>,
+    sensors: Query<(), With<Sensor>>,
+    session: Option<Res<NetSession>>,
+) {
+    let resimulated = resimulating(session);
+    for CollisionStarted(a, b) in collisions.read() {
+        for (object, other) in [(a, b), (b, a)] {
+            let Ok((throw, velocity, transform)) = thrown.get(*object) else {
+                continue;
+            };
+            let thrower_chain = links.get(*other).is_ok_and(|link| link.player == throw.by);
+            if *other == throw.by || thrower_chain || sensors.contains(*other) {
+                continue;
+            }
+
+            let strength = velocity.length();
+            if strength >= MIN_THROW_IMPACT {
+                impacts.write(ImpactEvent {
+                    position: transform.translation.xy(),
+                    strength,
+                    kind: ImpactKind::Throw,
+                    source: throw.by,
+                    target: Some(*other),
+                    resimulated,
+                });
+            }
+            throw.land(&mut commands.entity(*object));
+        }
+    }
+}
@@ -1,101 +1,361 @@
+use avian2d::prelude::*;
 use bevy::prelude::*;
 use bevy_tnua::{
     builtins::TnuaBuiltinDash,
     prelude::{TnuaBuiltinJump, TnuaBuiltinWalk, TnuaController},
 };
+use serde::{Deserialize, Serialize};
 
-use crate::ChainBase;
+use crate::grapple::Grapple;
+use crate::player::{Absent, InputSource};
+use crate::resume::{HeldActions, Resumable, ResumeTnua, jumping};
+use crate::status::{ChainLocked, DoubleJump, Slowed, SpeedBoost, Stunned, speed_factor};
+use crate::touch::TouchControls;
+use crate::{Chain, ChainBase};
 
-pub fn controls(
+/// How a character moves. The Tnua builtins are stored as-is and fed to the controller, so every
+/// knob Tnua exposes (coyote time, jump buffering, extra gravity, ...) can be tuned per character.
+///
+/// Tnua's defaults are tuned for a gravity of about 9.81, while our world runs at
+/// [`GRAVITY`](crate::GRAVITY) pixels per second squared, so all accelerations below are scaled
+/// up accordingly.
+#[derive(Component, Clone)]
+pub struct Movement {
+    pub speed: f32,
+    /// `desired_velocity` is overwritten every frame from the input direction.
+    pub walk: TnuaBuiltinWalk,
+    /// Fed every frame while jump is held. Releasing early applies `shorten_extra_gravity`, which
+    /// is what gives us variable jump height.
+    pub jump: TnuaBuiltinJump,
+    /// `displacement` is the full slam, `brake_to_speed` what is left of it if we haven't hit the
+    /// ground by the time it ends.
+    pub slam: TnuaBuiltinDash,
+    /// Upwards speed of a [`DoubleJump`] in the air, which Tnua knows nothing about.
+    pub air_jump_speed: f32,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self {
+            speed: 4000.0,
+            walk: TnuaBuiltinWalk {
+                // The `float_height` must be greater (even if by little) from the distance between the
+                // character's center and the lowest point of its collider.
+                float_height: 17.0,
+                acceleration: 600.0,
+                air_acceleration: 600.0,
+                // Still allowed to jump for a moment after running off a ledge.
+                coyote_time: 0.12,
+                // `TnuaBuiltinWalk` has many other fields for customizing the movement - but they have
+                // sensible defaults. Refer to the `TnuaBuiltinWalk`'s documentation to learn what they do.
+                ..Default::default()
+            },
+            jump: TnuaBuiltinJump {
+                height: 32.0 * 10.0,
+                // A jump pressed this long before landing still goes off once we touch the ground.
+                input_buffer_time: 0.15,
+                takeoff_extra_gravity: 3000.0,
+                fall_extra_gravity: 2000.0,
+                shorten_extra_gravity: 6000.0,
+                peak_prevention_at_upward_velocity: 100.0,
+                peak_prevention_extra_gravity: 2000.0,
+                ..Default::default()
+            },
+            slam: TnuaBuiltinDash {
+                displacement: -Vec3::Y * 1000.0,
+                desired_forward: None,
+                allow_in_air: true,
+                speed: 2500.0,
+                // Keep some downward speed so a slam that runs out in the air still falls fast.
+                brake_to_speed: 600.0,
+                acceleration: 20000.0,
+                brake_acceleration: 4000.0,
+                input_buffer_time: 0.1,
+            },
+            air_jump_speed: 900.0,
+        }
+    }
+}
+
+/// Everything a player asked for during one fixed tick.
+///
+/// Devices are only read by [`gather_input`]. The rest of the game works off this, so a
+/// [replay](crate::replay) can stand in for the devices.
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct InputFrame {
+    /// -1 for left, 1 for right.
+    pub walk: i8,
+    pub jump: bool,
+    pub slam: bool,
+    /// -1 to swing the chain base left, 1 for right.
+    pub chain: i8,
+    /// Held to hook the tip onto terrain, see [`grapple`](crate::grapple), or to hold on to
+    /// something, see [`grab`](crate::grab).
+    pub grapple: bool,
+}
+
+/// Something a player can do, for binding keys to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    WalkLeft,
+    WalkRight,
+    Jump,
+    Slam,
+    ChainLeft,
+    ChainRight,
+    Grapple,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::WalkLeft,
+        Action::WalkRight,
+        Action::Jump,
+        Action::Slam,
+        Action::ChainLeft,
+        Action::ChainRight,
+        Action::Grapple,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::WalkLeft => "Walk left",
+            Action::WalkRight => "Walk right",
+            Action::Jump => "Jump",
+            Action::Slam => "Slam",
+            Action::ChainLeft => "Swing chain left",
+            Action::ChainRight => "Swing chain right",
+            Action::Grapple => "Grapple and grab",
+        }
+    }
+}
+
+/// Keys of the keyboard player. Gamepads always use the same layout.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub walk_left: KeyCode,
+    pub walk_right: KeyCode,
+    pub jump: KeyCode,
+    pub slam: KeyCode,
+    pub chain_left: KeyCode,
+    pub chain_right: KeyCode,
+    pub grapple: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            walk_left: KeyCode::KeyA,
+            walk_right: KeyCode::KeyD,
+            jump: KeyCode::Space,
+            slam: KeyCode::KeyS,
+            chain_left: KeyCode::ArrowLeft,
+            chain_right: KeyCode::ArrowRight,
+            grapple: KeyCode::ArrowUp,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::WalkLeft => self.walk_left,
+            Action::WalkRight => self.walk_right,
+            Action::Jump => self.jump,
+            Action::Slam => self.slam,
+            Action::ChainLeft => self.chain_left,
+            Action::ChainRight => self.chain_right,
+            Action::Grapple => self.grapple,
+        }
+    }
+
+    pub fn key_mut(&mut self, action: Action) -> &mut KeyCode {
+        match action {
+            Action::WalkLeft => &mut self.walk_left,
+            Action::WalkRight => &mut self.walk_right,
+            Action::Jump => &mut self.jump,
+            Action::Slam => &mut self.slam,
+            Action::ChainLeft => &mut self.chain_left,
+            Action::ChainRight => &mut self.chain_right,
+            Action::Grapple => &mut self.grapple,
+        }
+    }
+}
+
+pub fn gather_input(
     keyboard: Res<ButtonInput<KeyCode>>,
-    players: Query<&mut TnuaController>,
+    bindings: Res<KeyBindings>,
+    touch: Res<TouchControls>,
     gamepads: Query<&Gamepad>,
-    mut base: Query<&mut ChainBase>,
+    players: Query<(&mut InputFrame, &InputSource, Has<Absent>)>,
 ) {
-    let mut gamepads = gamepads.into_iter();
+    for (mut frame, source, absent) in players {
+        *frame = InputFrame::default();
+        if absent {
+            continue;
+        }
 
-    for mut controller in players {
-        let mut direction = Vec3::ZERO;
-        if let Some(gamepad) = gamepads.next() {
-            if gamepad.pressed(GamepadButton::DPadLeft) {
-                direction -= Vec3::X;
-            }
-            if gamepad.pressed(GamepadButton::DPadRight) {
-                direction += Vec3::X;
-            }
-            if gamepad.pressed(GamepadButton::DPadUp) {
-                jump(&mut controller);
-            }
-            if gamepad.pressed(GamepadButton::DPadDown) {
-                slam(&mut controller);
+        match *source {
+            InputSource::Gamepad(gamepad) => {
+                // A pad that disconnected this frame may already have lost its `Gamepad`.
+                let Ok(gamepad) = gamepads.get(gamepad) else {
+                    continue;
+                };
+                frame.walk = axis(
+                    gamepad.pressed(GamepadButton::DPadLeft),
+                    gamepad.pressed(GamepadButton::DPadRight),
+                );
+                frame.jump = gamepad.pressed(GamepadButton::DPadUp);
+                frame.slam = gamepad.pressed(GamepadButton::DPadDown);
+                frame.chain = axis(
+                    gamepad.right_stick().x < -0.8,
+                    gamepad.right_stick().x > 0.8,
+                );
+                frame.grapple = gamepad.pressed(GamepadButton::RightTrigger2);
             }
-            if gamepad.right_stick().x > 0.8 {
-                base.iter_mut().for_each(|mut base| base.moveRight());
-            }
-            if gamepad.right_stick().x < -0.8 {
-                base.iter_mut().for_each(|mut base| base.moveLeft());
+            // The touch screen drives the same player as the keyboard.
+            InputSource::Keyboard => {
+                let pressed = |action| keyboard.pressed(bindings.key(action));
+                frame.walk = axis(
+                    pressed(Action::WalkLeft) || touch.walk < -0.3,
+                    pressed(Action::WalkRight) || touch.walk > 0.3,
+                );
+                frame.jump = pressed(Action::Jump) || touch.jump;
+                frame.slam = pressed(Action::Slam) || touch.slam;
+                frame.chain = axis(
+                    pressed(Action::ChainLeft) || touch.chain < -0.5,
+                    pressed(Action::ChainRight) || touch.chain > 0.5,
+                );
+                frame.grapple = pressed(Action::Grapple) || touch.grapple;
             }
+            // Filled in by the replay afterwards.
+            InputSource::Replay => {}
+        }
+    }
+}
+
+fn axis(negative: bool, positive: bool) -> i8 {
+    positive as i8 - negative as i8
+}
+
+pub fn controls(
+    mut commands: Commands,
+    time: Res<Time>,
+    players: Query<(
+        Entity,
+        &mut TnuaController,
+        &mut HeldActions,
+        Option<&ResumeTnua>,
+        &Movement,
+        &InputFrame,
+        &Chain,
+        Has<Stunned>,
+        Has<ChainLocked>,
+        Option<&SpeedBoost>,
+        Option<&Slowed>,
+        Option<&mut DoubleJump>,
+        &mut LinearVelocity,
+        Has<Grapple>,
+    )>,
+    mut bases: Query<&mut ChainBase>,
+) {
+    for (
+        entity,
+        mut controller,
+        mut held,
+        resume,
+        movement,
+        frame,
+        chain,
+        stunned,
+        chain_locked,
+        boost,
+        slowed,
+        double_jump,
+        mut velocity,
+        grappling,
+    ) in players
+    {
+        // Stunned players still get a basis to stand on, they just don't go anywhere.
+        let mut frame = if stunned {
+            InputFrame::default()
         } else {
-            if keyboard.pressed(KeyCode::KeyA) {
-                direction -= Vec3::X;
-            }
-            if keyboard.pressed(KeyCode::KeyD) {
-                direction += Vec3::X;
-            }
-            if keyboard.pressed(KeyCode::Space) {
-                jump(&mut controller);
-            }
-            if keyboard.pressed(KeyCode::KeyS) {
-                slam(&mut controller);
-            }
-            if keyboard.pressed(KeyCode::ArrowRight) {
-                base.iter_mut().for_each(|mut base| base.moveRight());
-            }
-            if keyboard.pressed(KeyCode::ArrowLeft) {
-                base.iter_mut().for_each(|mut base| base.moveLeft());
+            *frame
+        };
+        if chain_locked {
+            frame.chain = 0;
+        }
+        // Jump and slam reel the rope instead.
+        if grappling {
+            frame.jump = false;
+            frame.slam = false;
+        }
+        held.update(&controller, frame.jump, frame.slam, time.delta());
+        let jumping = jumping(&controller, resume);
+        // Must be fed every frame the button is held, Tnua shortens the jump as soon as we stop.
+        let jump = held.jump(&movement.jump, jumping, time.delta());
+        if let Some(resume) = resume {
+            resume.feed(&mut controller, movement, jump.is_some(), frame.slam);
+            commands.entity(entity).remove::<ResumeTnua>();
+        }
+        if let Some(jump) = jump {
+            controller.action(jump);
+        }
+        if let Some(mut double_jump) = double_jump {
+            air_jump(
+                &controller,
+                movement,
+                &mut double_jump,
+                &mut velocity,
+                frame.jump,
+            );
+        }
+        if frame.slam {
+            slam(&mut controller, movement);
+        }
+        if let Ok(mut base) = bases.get_mut(chain.base) {
+            match frame.chain {
+                1 => base.moveRight(),
+                -1 => base.moveLeft(),
+                _ => {}
             }
         }
 
-        walk(controller, direction);
+        // Absent players still get a basis, or Tnua lets them fall over.
+        let speed = movement.speed * speed_factor(boost, slowed);
+        walk(controller, movement, Vec3::X * frame.walk as f32, speed);
     }
 }
 
-fn walk(mut controller: Mut<'_, TnuaController>, direction: Vec3) {
+fn walk(mut controller: Mut<'_, TnuaController>, movement: &Movement, direction: Vec3, speed: f32) {
     // Feed the basis every frame. Even if the player doesn't move - just use `desired_velocity:
     // Vec3::ZERO`. `TnuaController` starts without a basis, which will make the character collider
     // just fall.
     controller.basis(TnuaBuiltinWalk {
         // The `desired_velocity` determines how the character will move.
-        desired_velocity: direction.normalize_or_zero() * 4000.0,
-        // The `float_height` must be greater (even if by little) from the distance between the
-        // character's center and the lowest point of its collider.
-        float_height: 17.0,
-        acceleration: 600.0,
-        air_acceleration: 600.0,
-        // `TnuaBuiltinWalk` has many other fields for customizing the movement - but they have
-        // sensible defaults. Refer to the `TnuaBuiltinWalk`'s documentation to learn what they do.
-        ..Default::default()
+        desired_velocity: direction.normalize_or_zero() * speed,
+        ..movement.walk.clone()
     });
 }
 
-fn jump(controller: &mut Mut<'_, TnuaController>) {
-    controller.action(TnuaBuiltinJump {
-        // The height is the only mandatory field of the jump button.
-        height: 32.0 * 10.0,
-        // `TnuaBuiltinJump` also has customization fields with sensible defaults.
-        ..Default::default()
-    });
+/// Jumps once more in the air on a fresh press of jump, with a [`DoubleJump`].
+fn air_jump(
+    controller: &TnuaController,
+    movement: &Movement,
+    double_jump: &mut DoubleJump,
+    velocity: &mut LinearVelocity,
+    pressed: bool,
+) {
+    if !controller.is_airborne().unwrap_or(false) {
+        double_jump.used = false;
+    } else if pressed && !double_jump.held && !double_jump.used {
+        double_jump.used = true;
+        velocity.y = movement.air_jump_speed;
+    }
+    double_jump.held = pressed;
 }
 
-fn slam(controller: &mut Mut<'_, TnuaController>) {
-    controller.action(TnuaBuiltinDash {
-        displacement: -Vec3::Y * 1000.0,
-        desired_forward: None,
-        allow_in_air: true,
-        speed: 1000000.00,
-        brake_to_speed: 0.0, // TODO,
-        acceleration: 10000.0,
-        brake_acceleration: 100.0,
-        input_buffer_time: 0.0,
-    });
+fn slam(controller: &mut Mut<'_, TnuaController>, movement: &Movement) {
+    controller.action(Resumable::new(movement.slam.clone()));
 }
@@ -0,0 +1,275 @@
+use bevy::prelude::*;
+
+use crate::player::{Appearance, CHARACTERS, InputSource, PlayerNumber, TINTS};
+use crate::touch::TouchControls;
+use crate::{GameState, spawn_player};
+
+pub const MAX_PLAYERS: usize = 4;
+
+/// A player that joined in the lobby, and what they picked.
+pub struct LobbySlot {
+    pub input: InputSource,
+    pub appearance: Appearance,
+    pub ready: bool,
+}
+
+/// Who is playing. Filled in the lobby and turned into players when the match starts.
+#[derive(Resource, Default)]
+pub struct Lobby {
+    pub slots: Vec<LobbySlot>,
+}
+
+impl Lobby {
+    fn slot_mut(&mut self, input: InputSource) -> Option<&mut LobbySlot> {
+        self.slots.iter_mut().find(|slot| slot.input == input)
+    }
+
+    fn join(&mut self, input: InputSource) {
+        if self.slots.len() >= MAX_PLAYERS {
+            return;
+        }
+        let number = self.slots.len();
+        info!("{input:?} joined as player {}", number + 1);
+        self.slots.push(LobbySlot {
+            input,
+            appearance: Appearance {
+                character: 0,
+                tint: number % TINTS.len(),
+            },
+            ready: false,
+        });
+    }
+
+    pub fn leave(&mut self, input: InputSource) {
+        self.slots.retain(|slot| slot.input != input);
+    }
+}
+
+/// What a device did in the lobby this frame.
+struct LobbyInput {
+    join: bool,
+    leave: bool,
+    ready: bool,
+    character: isize,
+    tint: isize,
+}
+
+#[derive(Component)]
+struct LobbyCards;
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lobby>()
+            .add_systems(OnEnter(GameState::Lobby), setup_lobby)
+            .add_systems(
+                Update,
+                (lobby_input, update_lobby_cards)
+                    .chain()
+                    .run_if(in_state(GameState::Lobby)),
+            )
+            .add_systems(OnEnter(GameState::Playing), spawn_lobby_players);
+    }
+}
+
+fn setup_lobby(mut commands: Commands, mut lobby: ResMut<Lobby>) {
+    for slot in &mut lobby.slots {
+        slot.ready = false;
+    }
+
+    commands
+        .spawn((
+            StateScoped(GameState::Lobby),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(24.0),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(
+                    "Enter / (A) to join\n\
+                     A D / D-pad left right: character   W S / D-pad up down: colour\n\
+                     Enter / (A): ready   Backspace / Select: leave   Esc: main menu",
+                ),
+                TextLayout::new_with_justify(JustifyText::Center),
+            ));
+            parent.spawn((
+                LobbyCards,
+                Node {
+                    column_gap: Val::Px(24.0),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn lobby_input(
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<GameState>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    touch: Res<TouchControls>,
+    gamepads: Query<(Entity, &Gamepad)>,
+) {
+    // Only flag the lobby as changed when something actually happened, the cards are rebuilt
+    // every time it is.
+    let mut changed = false;
+    let slots_before = lobby.slots.len();
+    let lobby_mut = lobby.bypass_change_detection();
+
+    // Pads that disconnect in the lobby just drop out, they can join again when they come back.
+    lobby_mut.slots.retain(|slot| match slot.input {
+        InputSource::Keyboard | InputSource::Replay => true,
+        InputSource::Gamepad(gamepad) => gamepads.contains(gamepad),
+    });
+
+    // On touch screens the jump button joins and readies up, as the keyboard player.
+    let keyboard_input = LobbyInput {
+        join: keyboard.any_just_pressed([KeyCode::Enter, KeyCode::Space])
+            || touch.jump_just_pressed,
+        leave: keyboard.just_pressed(KeyCode::Backspace),
+        ready: keyboard.any_just_pressed([KeyCode::Enter, KeyCode::Space])
+            || touch.jump_just_pressed,
+        character: axis(
+            keyboard.just_pressed(KeyCode::KeyA),
+            keyboard.just_pressed(KeyCode::KeyD),
+        ),
+        tint: axis(
+            keyboard.just_pressed(KeyCode::KeyS),
+            keyboard.just_pressed(KeyCode::KeyW),
+        ),
+    };
+    let gamepad_inputs = gamepads.iter().map(|(entity, gamepad)| {
+        let input = LobbyInput {
+            join: gamepad.any_just_pressed([GamepadButton::South, GamepadButton::Start]),
+            leave: gamepad.any_just_pressed([GamepadButton::East, GamepadButton::Select]),
+            ready: gamepad.any_just_pressed([GamepadButton::South, GamepadButton::Start]),
+            character: axis(
+                gamepad.just_pressed(GamepadButton::DPadLeft),
+                gamepad.just_pressed(GamepadButton::DPadRight),
+            ),
+            tint: axis(
+                gamepad.just_pressed(GamepadButton::DPadDown),
+                gamepad.just_pressed(GamepadButton::DPadUp),
+            ),
+        };
+        (InputSource::Gamepad(entity), input)
+    });
+
+    for (source, input) in
+        std::iter::once((InputSource::Keyboard, keyboard_input)).chain(gamepad_inputs)
+    {
+        let Some(slot) = lobby_mut.slot_mut(source) else {
+            if input.join {
+                lobby_mut.join(source);
+            }
+            continue;
+        };
+
+        if input.leave {
+            lobby_mut.leave(source);
+            continue;
+        }
+        if input.ready {
+            slot.ready = !slot.ready;
+            changed = true;
+        }
+        // Picks are locked in once ready.
+        if !slot.ready && (input.character != 0 || input.tint != 0) {
+            slot.appearance.character =
+                cycle(slot.appearance.character, input.character, CHARACTERS.len());
+            slot.appearance.tint = cycle(slot.appearance.tint, input.tint, TINTS.len());
+            changed = true;
+        }
+    }
+
+    if changed || lobby.slots.len() != slots_before {
+        lobby.set_changed();
+    }
+
+    if keyboard.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    } else if !lobby.slots.is_empty() && lobby.slots.iter().all(|slot| slot.ready) {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn axis(negative: bool, positive: bool) -> isize {
+    positive as isize - negative as isize
+}
+
+fn cycle(index: usize, step: isize, len: usize) -> usize {
+    (index as isize + step).rem_euclid(len as isize) as usize
+}
+
+fn update_lobby_cards(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    lobby: Res<Lobby>,
+    cards: Single<Entity, With<LobbyCards>>,
+) {
+    if !lobby.is_changed() {
+        return;
+    }
+
+    commands
+        .entity(*cards)
+        .despawn_related::<Children>()
+        .with_children(|parent| {
+            for (number, slot) in lobby.slots.iter().enumerate() {
+                let device = match slot.input {
+                    InputSource::Keyboard => "Keyboard",
+                    InputSource::Gamepad(_) => "Gamepad",
+                    InputSource::Replay => "Replay",
+                };
+                let status = if slot.ready { "Ready!" } else { "Choosing" };
+                parent
+                    .spawn((
+                        Node {
+                            width: Val::Px(160.0),
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            padding: UiRect::all(Val::Px(12.0)),
+                            row_gap: Val::Px(8.0),
+                            border: UiRect::all(Val::Px(3.0)),
+                            ..default()
+                        },
+                        BorderColor(slot.appearance.color()),
+                        BackgroundColor(Color::BLACK.with_alpha(0.5)),
+                    ))
+                    .with_children(|card| {
+                        card.spawn(Text::new(format!("P{}", number + 1)));
+                        card.spawn((
+                            ImageNode::new(asset_server.load(slot.appearance.image()))
+                                .with_color(slot.appearance.color()),
+                            Node {
+                                width: Val::Px(96.0),
+                                height: Val::Px(96.0),
+                                ..default()
+                            },
+                        ));
+                        card.spawn(Text::new(device));
+                        card.spawn(Text::new(status));
+                    });
+            }
+        });
+}
+
+fn spawn_lobby_players(mut commands: Commands, asset_server: Res<AssetServer>, lobby: Res<Lobby>) {
+    for (number, slot) in lobby.slots.iter().enumerate() {
+        spawn_player(
+            &mut commands,
+            &asset_server,
+            PlayerNumber(number),
+            slot.input,
+            slot.appearance,
+        );
+    }
+}
@@ -1,30 +1,92 @@
-use std::time::Duration;
-
+use animation::CharacterAnimation;
 use avian2d::prelude::*;
-use bevy::input::gamepad::GamepadConnectionEvent;
+use bevy::asset::AssetMetaCheck;
 use bevy::prelude::*;
-use bevy::{asset::AssetMetaCheck, input::gamepad::GamepadEvent};
 use bevy_ecs_tilemap::TilemapPlugin;
-use delete_after::{DeleteAt, delete_at};
-use input::controls;
+use delete_after::{delete_at_fixed, delete_at_virtual};
+use input::{InputFrame, Movement, controls, gather_input};
+use lobby::LobbyPlugin;
+use particles::{ParticleEffect, ParticleEvent};
+use player::{Appearance, InputSource, PlayerNumber};
+use resume::HeldActions;
+use round::{Health, STOCKS, Score, Stocks};
 use tilemap::helpers::tiled::TiledMap;
 
 use bevy_tnua::prelude::*;
 use bevy_tnua_avian2d::*;
 
+mod animation;
+mod audio;
+mod camera;
+mod chain;
+mod chain_rope;
 mod cursed_mouse_input;
 mod delete_after;
+mod determinism;
+mod grab;
+mod grapple;
+mod hazard;
+mod hud;
+mod impact;
 mod input;
+mod lobby;
+mod menu;
+mod net;
+mod particles;
+mod pickup;
+mod player;
+mod replay;
+mod resume;
+mod round;
+mod save;
+mod settings;
+mod status;
 mod tilemap;
+mod touch;
 
 const GRAVITY: f32 = 980.0;
 const CHAIN_LINK_COUNT: usize = 10;
+const LINK_MASS: f32 = 0.0005;
+
+#[derive(States, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[states(scoped_entities)]
+pub enum GameState {
+    #[default]
+    MainMenu,
+    /// Players join and pick their character.
+    Lobby,
+    Playing,
+}
+
+/// Whether the match is frozen behind the pause menu.
+#[derive(SubStates, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[source(GameState = GameState::Playing)]
+pub enum Pause {
+    #[default]
+    Running,
+    Paused,
+}
 
 #[derive(Component)]
 pub struct Player;
 
+/// A link of the chain belonging to `player`.
 #[derive(Component)]
-pub struct ChainLink;
+pub struct ChainLink {
+    pub player: Entity,
+}
+
+/// Everything `spawn_chain` created for a player, so it can be controlled and torn down along
+/// with them.
+#[derive(Component, Clone)]
+pub struct Chain {
+    /// From the base at the player to the tip.
+    pub links: Vec<Entity>,
+    /// Every joint of the chain, including `base`.
+    pub joints: Vec<Entity>,
+    /// The joint attaching the chain to the player, carrying the [`ChainBase`].
+    pub base: Entity,
+}
 
 #[derive(Component, Clone, Copy)]
 pub struct ChainBase {
@@ -55,25 +117,70 @@ fn main() {
                 meta_check: AssetMetaCheck::Never,
                 ..default()
             }),
+            // `PhysicsDebugPlugin` is added by the free camera, toggled with F3.
             PhysicsPlugins::default(),
-            //PhysicsDebugPlugin::default(),
             TnuaControllerPlugin::new(FixedUpdate),
             TnuaAvian2dPlugin::new(FixedUpdate),
         ))
+        .init_state::<GameState>()
+        .add_sub_state::<Pause>()
+        .add_plugins((
+            animation::AnimationPlugin,
+            audio::SoundPlugin,
+            camera::CameraPlugin,
+            chain_rope::ChainRopePlugin,
+            hud::HudPlugin,
+            impact::ImpactPlugin,
+            LobbyPlugin,
+            menu::MenuPlugin,
+            net::NetPlugin,
+            particles::ParticlePlugin,
+            replay::ReplayPlugin,
+            round::RoundPlugin,
+            save::SavePlugin,
+            settings::SettingsPlugin,
+            touch::TouchControlsPlugin,
+        ))
         .init_asset::<TiledMap>()
         .insert_resource(Gravity(Vec2::NEG_Y * GRAVITY))
         .add_plugins(TilemapPlugin)
         .add_plugins(tilemap::helpers::tiled::TiledMapPlugin)
         .add_systems(Startup, setup)
+        .add_plugins(determinism::DeterminismPlugin)
+        .add_plugins((
+            status::StatusPlugin,
+            pickup::PickupPlugin,
+            hazard::HazardPlugin,
+            grapple::GrapplePlugin,
+            grab::GrabPlugin,
+        ))
+        .init_resource::<tilemap::CurrentLevel>()
         .add_systems(Startup, tilemap::setup)
         .add_systems(
             FixedUpdate,
-            (controls.in_set(TnuaUserControlsSystemSet), woosh_chain),
+            (
+                (
+                    gather_input.run_if(round::fighting),
+                    controls,
+                    chainControll,
+                )
+                    .chain()
+                    .in_set(TnuaUserControlsSystemSet),
+                // Already left behind the first time round.
+                woosh_chain.run_if(not(net::resimulating)),
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(Update, delete_at_virtual)
+        .add_systems(FixedUpdate, delete_at_fixed)
+        .add_systems(
+            Update,
+            (
+                player::gamepad_connections.run_if(in_state(GameState::Playing)),
+                // Backspace also goes back in the pause menu. Online, the peer would never know.
+                player::leave.run_if(in_state(Pause::Running).and(not(net::online))),
+            ),
         )
-        .add_systems(Update, camera_follow_player)
-        .add_systems(Update, delete_at)
-        .add_systems(Update, chainControll)
-        .add_systems(Update, spawn_player_on_gamepad_connect)
         .run();
 }
 
@@ -84,8 +191,6 @@ fn chainControll(chainBases: Query<(&mut RevoluteJoint, &ChainBase)>) {
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn(Camera2d);
-
     let player_scale = 0.1;
 
     // DUCK
@@ -100,37 +205,28 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         Collider::circle(10.0 / player_scale),
         Mass(1.0), // TODO
     ));
-
-    // Music
-    commands.spawn((
-        AudioPlayer::new(asset_server.load("ost.ogg")),
-        PlaybackSettings::LOOP,
-    ));
-
-    // Keyboard
-    spawn_player(&mut commands, &asset_server);
 }
 
-fn spawn_player_on_gamepad_connect(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut event_reader: EventReader<GamepadEvent>,
-) {
-    for event in event_reader.read() {
-        if let GamepadEvent::Connection(event) = event {
-            if event.connected() {
-                spawn_player(&mut commands, &asset_server);
-            }
-        }
-    }
+/// Where a player starts out, and comes back to after being knocked out.
+pub fn spawn_point(number: PlayerNumber) -> Vec2 {
+    Vec2::new(20.0 + number.0 as f32 * 80.0, 0.1)
 }
 
-fn spawn_player(commands: &mut Commands, asset_server: &Res<AssetServer>) {
+/// Spawns the player and then their chain.
+pub fn spawn_player(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    number: PlayerNumber,
+    input_source: InputSource,
+    appearance: Appearance,
+) -> Entity {
+    let position = spawn_point(number);
     let player = commands
         .spawn((
-            Transform::from_xyz(20.0, 0.1, 0.0),
+            Transform::from_translation(position.extend(0.0)),
             Sprite {
-                image: asset_server.load("character2.png"),
+                image: asset_server.load(appearance.image()),
+                color: appearance.color(),
                 custom_size: Some(Vec2::new(64.0, 64.0)),
                 ..Default::default()
             },
@@ -138,118 +234,99 @@ fn spawn_player(commands: &mut Commands, asset_server: &Res<AssetServer>) {
             Collider::capsule(32.0, 0.0),
             Friction::new(0.2),
             LockedAxes::ROTATION_LOCKED,
-            Player,
+            // Bundles only take so many components, so these go together.
+            (Player, number, input_source, appearance),
+            CharacterAnimation::new(asset_server.load(appearance.animations())),
+            InputFrame::default(),
+            (Health::new(100.0), Stocks(STOCKS), Score::default()),
+            Movement::default(),
+            HeldActions::default(),
             TnuaController::default(),
             // A sensor shape is not strictly necessary, but without it we'll get weird results.
             TnuaAvian2dSensorShape(Collider::rectangle(31.0, 31.0)),
         ))
         .id();
-    spawn_chain(player, commands, &asset_server);
+    let chain = spawn_chain(player, position, commands);
+    commands.entity(player).insert(chain);
+    player
 }
 
-fn spawn_chain(player: Entity, commands: &mut Commands, asset_server: &Res<AssetServer>) {
-    let mut chain_link = vec![
-        commands
-            .spawn((
-                Transform::from_xyz(21.0, 500.1, 0.0).with_scale(Vec3::ONE * 0.1),
-                RigidBody::Dynamic,
-                ExternalImpulse::ZERO,
-                Collider::capsule(75.0, 80.0),
-                Sprite {
-                    image: asset_server.load("chain.png"),
-                    custom_size: Some(Vec2::new(100.0, 200.0)),
-                    ..Default::default()
-                },
-                Mass(0.0005),
-            ))
-            .id(),
-    ];
-    for i in 1..CHAIN_LINK_COUNT {
-        chain_link.push(
-            commands
-                .spawn((
-                    ChainLink,
-                    Transform::from_xyz(21.0, i as f32 + 500.1, 0.0).with_scale(Vec3::ONE * 0.1),
-                    RigidBody::Dynamic,
-                    ExternalImpulse::ZERO,
-                    Collider::capsule(75.0, 80.0),
-                    Sprite {
-                        image: asset_server.load("chain.png"),
-                        custom_size: Some(Vec2::new(100.0, 200.0)),
-                        ..Default::default()
-                    },
-                    Mass(0.0005),
-                ))
-                .id(),
-        );
-    }
+/// Only the physics of the chain, it is drawn by [`chain_rope`].
+fn spawn_chain(player: Entity, position: Vec2, commands: &mut Commands) -> Chain {
+    let chain_link: Vec<Entity> = (0..CHAIN_LINK_COUNT)
+        .map(|i| {
+            let translation = Vec3::new(position.x + 1.0, position.y + i as f32 + 500.0, 0.0);
+            spawn_link(commands, player, translation)
+        })
+        .collect();
 
+    let mut joints = Vec::with_capacity(CHAIN_LINK_COUNT);
     for i in 0..(CHAIN_LINK_COUNT - 1) {
-        let c1 = chain_link[i];
-        let c2 = chain_link[i + 1];
-        commands.spawn(
-            RevoluteJoint::new(c1, c2)
-                .with_local_anchor_1(Vec2::new(0.0, -10.0))
-                .with_local_anchor_2(Vec2::new(0.0, 10.0)),
-        );
+        joints.push(spawn_link_joint(commands, chain_link[i], chain_link[i + 1]));
     }
 
-    commands.spawn((
-        RevoluteJoint::new(player, chain_link[0])
-            .with_local_anchor_1(Vec2::new(0.0, 50.0))
-            .with_local_anchor_2(Vec2::new(0.0, 10.0)),
-        ChainBase { pos: 0.0 },
-    ));
-}
-
-fn camera_follow_player(
-    players: Query<&Transform, (With<Player>, Without<Camera2d>)>,
-    cameras: Query<&mut Transform, (With<Camera2d>, Without<Player>)>,
-    _time: Res<Time>,
-) {
-    let mut min = Vec2::INFINITY;
-    let mut max = Vec2::NEG_INFINITY;
+    let base = commands
+        .spawn((
+            RevoluteJoint::new(player, chain_link[0])
+                .with_local_anchor_1(Vec2::new(0.0, 50.0))
+                .with_local_anchor_2(Vec2::new(0.0, 10.0)),
+            ChainBase { pos: 0.0 },
+        ))
+        .id();
+    joints.push(base);
 
-    for player in players {
-        min = min.min(player.translation.xy());
-        max = max.max(player.translation.xy());
+    Chain {
+        links: chain_link,
+        joints,
+        base,
     }
+}
 
-    let center = (min + max) / 2.0;
-
-    for mut camera in cameras {
-        camera.translation.x = center.x;
-        camera.translation.y = center.y;
+fn spawn_link(commands: &mut Commands, player: Entity, translation: Vec3) -> Entity {
+    commands
+        .spawn((
+            ChainLink { player },
+            Transform::from_translation(translation).with_scale(Vec3::ONE * 0.1),
+            RigidBody::Dynamic,
+            ExternalImpulse::ZERO,
+            Collider::capsule(75.0, 80.0),
+            Mass(LINK_MASS),
+            CollisionEventsEnabled,
+            // For the tip to know what it touches, to grapple onto.
+            CollidingEntities::default(),
+        ))
+        .id()
+}
 
-        //const LERP_SPEED: f32 = 10.0;
-        //camera.translation = camera
-        //    .translation
-        //    .lerp(player.translation, time.delta().as_secs_f32() * LERP_SPEED);
-    }
+/// Joins `next` to the end of `previous` pointing away from the base.
+fn spawn_link_joint(commands: &mut Commands, previous: Entity, next: Entity) -> Entity {
+    commands
+        .spawn(
+            RevoluteJoint::new(previous, next)
+                .with_local_anchor_1(Vec2::new(0.0, -10.0))
+                .with_local_anchor_2(Vec2::new(0.0, 10.0)),
+        )
+        .id()
 }
 
+/// Leaves a trail behind every link moving fast enough to crack.
 fn woosh_chain(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    player: Single<&LinearVelocity, (With<Player>, Without<ChainLink>)>,
-    chain_links: Query<(&LinearVelocity, &Transform), (With<ChainLink>, Without<Player>)>,
+    mut particles: EventWriter<ParticleEvent>,
+    players: Query<&LinearVelocity, With<Player>>,
+    chain_links: Query<(&LinearVelocity, &Transform, &ChainLink)>,
 ) {
-    for (link_velocity, link_pos) in chain_links {
+    for (link_velocity, link_pos, link) in chain_links {
+        let Ok(player) = players.get(link.player) else {
+            continue;
+        };
         let delta_v = link_velocity.0 - player.0;
         let velocity = delta_v.length();
         if velocity > 1000.0 {
-            commands.spawn((
-                Transform::from_translation(link_pos.translation),
-                Sprite {
-                    image: asset_server.load("ducky.png"),
-                    custom_size: Some(Vec2::new(10.0, 10.0)),
-                    ..Default::default()
-                },
-                RigidBody::Dynamic,
-                ExternalImpulse::new(delta_v / 2.0),
-                Mass(1.0),
-                DeleteAt::after(Duration::from_secs(5)),
-            ));
+            particles.write(ParticleEvent {
+                effect: ParticleEffect::WhipTrail,
+                position: link_pos.translation.xy(),
+                velocity: delta_v,
+            });
         }
     }
 }
@@ -0,0 +1,473 @@
+//! The main menu, the pause menu and the settings pages behind both.
+//!
+//! Menus work with the keyboard (arrows or WASD, Enter, Escape), any gamepad (D-pad, (A), (B)) and
+//! by clicking or tapping the items. Left and right change the value of the focused setting.
+
+use avian2d::prelude::*;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::input::Action;
+use crate::net;
+use crate::settings::{RESOLUTIONS, Settings};
+use crate::{GameState, Pause};
+
+const VOLUME_STEP: f32 = 0.1;
+const SHAKE_STEP: f32 = 0.25;
+const MAX_SHAKE: f32 = 2.0;
+const FOCUSED: Color = Color::srgba(1.0, 1.0, 1.0, 0.25);
+const UNFOCUSED: Color = Color::srgba(1.0, 1.0, 1.0, 0.05);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MenuPage {
+    Main,
+    Pause,
+    Settings,
+    Controls,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum VolumeBus {
+    Master,
+    Music,
+    Sfx,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MenuItem {
+    Play,
+    Resume,
+    Settings,
+    Controls,
+    Quit,
+    QuitToMainMenu,
+    Volume(VolumeBus),
+    ScreenShake,
+    Fullscreen,
+    Resolution,
+    Rebind(Action),
+    ResetBindings,
+    Back,
+}
+
+impl MenuPage {
+    fn title(self) -> &'static str {
+        match self {
+            MenuPage::Main => "Chainwhips",
+            MenuPage::Pause => "Paused",
+            MenuPage::Settings => "Settings",
+            MenuPage::Controls => "Controls",
+        }
+    }
+
+    fn items(self) -> Vec<MenuItem> {
+        match self {
+            MenuPage::Main => {
+                let mut items = vec![MenuItem::Play, MenuItem::Settings];
+                // A browser tab can't close itself.
+                if cfg!(not(target_arch = "wasm32")) {
+                    items.push(MenuItem::Quit);
+                }
+                items
+            }
+            MenuPage::Pause => vec![
+                MenuItem::Resume,
+                MenuItem::Settings,
+                MenuItem::QuitToMainMenu,
+            ],
+            MenuPage::Settings => vec![
+                MenuItem::Volume(VolumeBus::Master),
+                MenuItem::Volume(VolumeBus::Music),
+                MenuItem::Volume(VolumeBus::Sfx),
+                MenuItem::ScreenShake,
+                MenuItem::Fullscreen,
+                MenuItem::Resolution,
+                MenuItem::Controls,
+                MenuItem::Back,
+            ],
+            MenuPage::Controls => Action::ALL
+                .into_iter()
+                .map(MenuItem::Rebind)
+                .chain([MenuItem::ResetBindings, MenuItem::Back])
+                .collect(),
+        }
+    }
+}
+
+/// The open menu pages, the last one is shown. Empty while no menu is open.
+#[derive(Resource, Default)]
+struct Menu {
+    pages: Vec<MenuPage>,
+    /// Index of the focused item on the shown page.
+    focus: usize,
+    /// Waiting for a key to bind to this action.
+    rebinding: Option<Action>,
+}
+
+impl Menu {
+    fn open(&mut self, page: MenuPage) {
+        self.pages.push(page);
+        self.focus = 0;
+    }
+
+    fn page(&self) -> Option<MenuPage> {
+        self.pages.last().copied()
+    }
+}
+
+/// What a device asked of the menu this frame.
+#[derive(Default)]
+struct MenuInput {
+    up: bool,
+    down: bool,
+    left: bool,
+    right: bool,
+    confirm: bool,
+    back: bool,
+}
+
+impl MenuInput {
+    fn any(&self) -> bool {
+        self.up || self.down || self.left || self.right || self.confirm || self.back
+    }
+}
+
+#[derive(Component)]
+struct MenuRoot;
+
+#[derive(Component)]
+struct MenuButton(usize);
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Menu>()
+            .add_systems(OnEnter(GameState::MainMenu), open(MenuPage::Main))
+            .add_systems(OnExit(GameState::MainMenu), close)
+            .add_systems(OnEnter(Pause::Paused), (open(MenuPage::Pause), pause_time))
+            .add_systems(OnExit(Pause::Paused), (close, resume_time))
+            // The peer can't be paused with us.
+            .add_systems(
+                Update,
+                pause_game.run_if(in_state(Pause::Running).and(not(net::online))),
+            )
+            .add_systems(
+                Update,
+                (
+                    (rebind_key, navigate_menu)
+                        .chain()
+                        .run_if(|menu: Res<Menu>| !menu.pages.is_empty()),
+                    rebuild_menu,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn open(page: MenuPage) -> impl Fn(ResMut<Menu>) {
+    move |mut menu| {
+        *menu = Menu::default();
+        menu.open(page);
+    }
+}
+
+fn close(mut menu: ResMut<Menu>) {
+    *menu = Menu::default();
+}
+
+/// Freezes the whole simulation: without virtual time `FixedUpdate` doesn't run, which stops
+/// input, Tnua and avian alike. Physics time is paused as well, so nothing steps it by accident.
+fn pause_time(mut time: ResMut<Time<Virtual>>, mut physics_time: ResMut<Time<Physics>>) {
+    time.pause();
+    physics_time.pause();
+}
+
+fn resume_time(mut time: ResMut<Time<Virtual>>, mut physics_time: ResMut<Time<Physics>>) {
+    time.unpause();
+    physics_time.unpause();
+}
+
+fn pause_game(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut next_pause: ResMut<NextState<Pause>>,
+) {
+    if keyboard.just_pressed(KeyCode::Escape)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::Start))
+    {
+        next_pause.set(Pause::Paused);
+    }
+}
+
+/// While rebinding, the next key pressed is bound. Escape cancels.
+fn rebind_key(
+    mut keyboard: ResMut<ButtonInput<KeyCode>>,
+    mut menu: ResMut<Menu>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(action) = menu.rebinding else {
+        return;
+    };
+    let Some(key) = keyboard.get_just_pressed().next().copied() else {
+        return;
+    };
+    if key != KeyCode::Escape {
+        *settings.bindings.key_mut(action) = key;
+    }
+    menu.rebinding = None;
+    // Or the menu would act on it too.
+    keyboard.clear_just_pressed(key);
+}
+
+fn navigate_menu(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut menu: ResMut<Menu>,
+    mut settings: ResMut<Settings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_pause: ResMut<NextState<Pause>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // Rebinding eats all keys, including the ones for the menu.
+    if menu.rebinding.is_some() {
+        return;
+    }
+    let Some(page) = menu.page() else {
+        return;
+    };
+    let items = page.items();
+
+    let mut input = MenuInput {
+        up: keyboard.any_just_pressed([KeyCode::ArrowUp, KeyCode::KeyW]),
+        down: keyboard.any_just_pressed([KeyCode::ArrowDown, KeyCode::KeyS]),
+        left: keyboard.any_just_pressed([KeyCode::ArrowLeft, KeyCode::KeyA]),
+        right: keyboard.any_just_pressed([KeyCode::ArrowRight, KeyCode::KeyD]),
+        confirm: keyboard.any_just_pressed([KeyCode::Enter, KeyCode::Space]),
+        back: keyboard.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]),
+    };
+    for gamepad in &gamepads {
+        input.up |= gamepad.just_pressed(GamepadButton::DPadUp);
+        input.down |= gamepad.just_pressed(GamepadButton::DPadDown);
+        input.left |= gamepad.just_pressed(GamepadButton::DPadLeft);
+        input.right |= gamepad.just_pressed(GamepadButton::DPadRight);
+        input.confirm |= gamepad.just_pressed(GamepadButton::South);
+        input.back |= gamepad.any_just_pressed([GamepadButton::East, GamepadButton::Start]);
+    }
+
+    let mut focus = menu.focus;
+    for (interaction, MenuButton(index)) in &buttons {
+        if *interaction == Interaction::Pressed {
+            focus = *index;
+            input.confirm = true;
+        }
+    }
+    if !input.any() {
+        return;
+    }
+
+    if input.up {
+        focus = (focus + items.len() - 1) % items.len();
+    }
+    if input.down {
+        focus = (focus + 1) % items.len();
+    }
+    if focus != menu.focus {
+        menu.focus = focus;
+    }
+
+    let step = input.right as i32 - input.left as i32;
+    let item = if input.back {
+        MenuItem::Back
+    } else {
+        items[focus]
+    };
+    if !input.confirm && !input.back && step == 0 {
+        return;
+    }
+
+    match item {
+        MenuItem::Play if input.confirm => next_state.set(GameState::Lobby),
+        MenuItem::Resume if input.confirm => next_pause.set(Pause::Running),
+        MenuItem::Settings if input.confirm => menu.open(MenuPage::Settings),
+        MenuItem::Controls if input.confirm => menu.open(MenuPage::Controls),
+        MenuItem::Quit if input.confirm => {
+            exit.write(AppExit::Success);
+        }
+        MenuItem::QuitToMainMenu if input.confirm => next_state.set(GameState::MainMenu),
+        MenuItem::Volume(bus) => {
+            let volume = match bus {
+                VolumeBus::Master => &mut settings.master_volume,
+                VolumeBus::Music => &mut settings.music_volume,
+                VolumeBus::Sfx => &mut settings.sfx_volume,
+            };
+            *volume = adjust(*volume, step, input.confirm, VOLUME_STEP, 1.0);
+        }
+        MenuItem::ScreenShake => {
+            settings.screen_shake = adjust(
+                settings.screen_shake,
+                step,
+                input.confirm,
+                SHAKE_STEP,
+                MAX_SHAKE,
+            );
+        }
+        MenuItem::Fullscreen => settings.fullscreen = !settings.fullscreen,
+        MenuItem::Resolution => {
+            let step = if step == 0 { 1 } else { step };
+            settings.resolution =
+                (settings.resolution as i32 + step).rem_euclid(RESOLUTIONS.len() as i32) as usize;
+        }
+        MenuItem::Rebind(action) if input.confirm => menu.rebinding = Some(action),
+        MenuItem::ResetBindings if input.confirm => settings.bindings = default(),
+        MenuItem::Back => match page {
+            // Backing out of the pause menu is resuming.
+            MenuPage::Pause => next_pause.set(Pause::Running),
+            // There is nothing behind the main menu.
+            MenuPage::Main => {}
+            MenuPage::Settings | MenuPage::Controls => {
+                let previous = menu.pages.len() - 1;
+                menu.pages.truncate(previous);
+                menu.focus = 0;
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Steps `value` by `step` increments, or wraps around through all of them on confirm.
+fn adjust(value: f32, step: i32, confirm: bool, increment: f32, max: f32) -> f32 {
+    let value = if confirm && value >= max - increment / 2.0 {
+        0.0
+    } else if confirm {
+        value + increment
+    } else {
+        value + step as f32 * increment
+    };
+    // Keep clear of float drift, so 100% stays 100%.
+    ((value / increment).round() * increment).clamp(0.0, max)
+}
+
+fn rebuild_menu(
+    mut commands: Commands,
+    menu: Res<Menu>,
+    settings: Res<Settings>,
+    roots: Query<Entity, With<MenuRoot>>,
+) {
+    if !menu.is_changed() && !settings.is_changed() {
+        return;
+    }
+    for root in &roots {
+        commands.entity(root).despawn();
+    }
+    let Some(page) = menu.page() else {
+        return;
+    };
+
+    commands
+        .spawn((
+            MenuRoot,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(12.0),
+                ..default()
+            },
+            BackgroundColor(Color::BLACK.with_alpha(0.75)),
+            // Above everything, touch controls included, so taps reach the items.
+            GlobalZIndex(20),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(page.title()),
+                TextFont::from_font_size(48.0),
+                Node {
+                    margin: UiRect::bottom(Val::Px(24.0)),
+                    ..default()
+                },
+            ));
+
+            for (index, item) in page.items().into_iter().enumerate() {
+                let focused = index == menu.focus;
+                parent
+                    .spawn((
+                        MenuButton(index),
+                        Button,
+                        Node {
+                            width: Val::Px(420.0),
+                            padding: UiRect::axes(Val::Px(16.0), Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        BackgroundColor(if focused { FOCUSED } else { UNFOCUSED }),
+                    ))
+                    .with_child(Text::new(label(item, &menu, &settings)));
+            }
+
+            parent.spawn((
+                Text::new(
+                    "Arrows / D-pad: choose and change   Enter / (A): select   Esc / (B): back",
+                ),
+                TextFont::from_font_size(14.0),
+                Node {
+                    margin: UiRect::top(Val::Px(24.0)),
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn label(item: MenuItem, menu: &Menu, settings: &Settings) -> String {
+    let percent = |value: f32| format!("< {:.0}% >", value * 100.0);
+    match item {
+        MenuItem::Play => "Play".to_string(),
+        MenuItem::Resume => "Resume".to_string(),
+        MenuItem::Settings => "Settings".to_string(),
+        MenuItem::Controls => "Controls".to_string(),
+        MenuItem::Quit => "Quit".to_string(),
+        MenuItem::QuitToMainMenu => "Quit to main menu".to_string(),
+        MenuItem::Volume(VolumeBus::Master) => {
+            format!("Master volume {}", percent(settings.master_volume))
+        }
+        MenuItem::Volume(VolumeBus::Music) => format!("Music {}", percent(settings.music_volume)),
+        MenuItem::Volume(VolumeBus::Sfx) => format!("Effects {}", percent(settings.sfx_volume)),
+        MenuItem::ScreenShake => format!("Screen shake {}", percent(settings.screen_shake)),
+        MenuItem::Fullscreen => {
+            format!(
+                "Fullscreen: {}",
+                if settings.fullscreen { "on" } else { "off" }
+            )
+        }
+        MenuItem::Resolution => {
+            let (width, height) = RESOLUTIONS[settings.resolution % RESOLUTIONS.len()];
+            format!("Window size < {width} x {height} >")
+        }
+        MenuItem::Rebind(action) if menu.rebinding == Some(action) => {
+            format!("{}: press a key...", action.label())
+        }
+        MenuItem::Rebind(action) => {
+            format!(
+                "{}: {}",
+                action.label(),
+                key_name(settings.bindings.key(action))
+            )
+        }
+        MenuItem::ResetBindings => "Reset to defaults".to_string(),
+        MenuItem::Back => "Back".to_string(),
+    }
+}
+
+/// `KeyA` reads better as `A`.
+fn key_name(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    name.strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+        .unwrap_or(&name)
+        .to_string()
+}
@@ -0,0 +1,789 @@
+//! Online play between two clients, with rollback.
+//!
+//! Start both clients with the address to listen on, where the other one is, and which player
+//! they control. Both skip the lobby and play against each other until the window is closed:
+//!
+//! ```text
+//! chainwhips --bind 127.0.0.1:7000 --peer 127.0.0.1:7001 --player 1
+//! chainwhips --bind 127.0.0.1:7001 --peer 127.0.0.1:7000 --player 2
+//! ```
+//!
+//! `--latency <ms>`, `--jitter <ms>` and `--loss <percent>` make the connection behave worse than
+//! it is, see [`LinkConditions`].
+//!
+//! Every fixed tick each client sends its [`InputFrame`] for [`INPUT_DELAY`] ticks later, and
+//! carries on with a guess for the other player's input: whatever they did last. When the real
+//! input arrives and differs from the guess, the world is reset to a [`Snapshot`] from that tick
+//! and the ticks since are simulated again, all before the next frame is drawn.
+//!
+//! Every [`CHECKSUM_INTERVAL`] ticks the clients also compare the [`StateChecksum`] of a tick
+//! neither can roll back past anymore, and complain if they disagree. Build both with
+//! `--features deterministic` to keep that from happening between different machines.
+
+mod snapshot;
+mod transport;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bevy::app::FixedMain;
+use bevy::ecs::entity_disabling::Disabled;
+use bevy::prelude::*;
+use bevy_tnua::prelude::TnuaUserControlsSystemSet;
+
+use crate::GameState;
+use crate::delete_after::Despawned;
+use crate::determinism::{SimulationTick, StateChecksum, take_checksum};
+use crate::input::{InputFrame, controls, gather_input};
+use crate::lobby::{Lobby, LobbySlot};
+use crate::player::{Appearance, CHARACTERS, InputSource, PlayerNumber, TINTS};
+use crate::round::{MatchPhase, fighting};
+use snapshot::{Snapshot, take_snapshot};
+use transport::{LinkConditions, Transport};
+
+/// Ticks between reading local input and using it. Hides that much latency without rolling back.
+const INPUT_DELAY: u32 = 2;
+/// How many ticks we may run ahead of the last input we have from the peer, before waiting for
+/// them.
+const MAX_PREDICTION: u32 = 12;
+/// Most of our inputs sent in one packet, oldest the peer doesn't have yet first.
+const MAX_RESEND: usize = 64;
+/// Ticks between checksums compared with the peer.
+const CHECKSUM_INTERVAL: u32 = 64;
+/// Slowed down to this speed while ahead of the peer, so they can catch up.
+const CATCH_UP_SPEED: f32 = 0.9;
+
+/// What goes over the wire.
+#[derive(PartialEq, Debug)]
+enum Packet {
+    /// Sent until the peer answers, at the start of every match.
+    Hello { player: u8, match_number: u8 },
+    Inputs {
+        match_number: u8,
+        first_tick: u32,
+        frames: Vec<InputFrame>,
+        /// Ticks of the receiver's input the sender has, without gaps.
+        confirmed: u32,
+        /// How many ticks the sender thinks it is ahead, see [`sync_time`].
+        advantage: i8,
+    },
+    /// The sender's [`StateChecksum`] for a tick.
+    Checksum {
+        match_number: u8,
+        tick: u32,
+        value: u64,
+    },
+}
+
+impl Packet {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Packet::Hello {
+                player,
+                match_number,
+            } => vec![0, *player, *match_number],
+            Packet::Inputs {
+                match_number,
+                first_tick,
+                frames,
+                confirmed,
+                advantage,
+            } => {
+                let mut bytes = vec![1, *match_number];
+                bytes.extend(first_tick.to_le_bytes());
+                bytes.extend(confirmed.to_le_bytes());
+                bytes.push(*advantage as u8);
+                bytes.push(frames.len() as u8);
+                bytes.extend(frames.iter().map(|frame| encode_frame(*frame)));
+                bytes
+            }
+            Packet::Checksum {
+                match_number,
+                tick,
+                value,
+            } => {
+                let mut bytes = vec![2, *match_number];
+                bytes.extend(tick.to_le_bytes());
+                bytes.extend(value.to_le_bytes());
+                bytes
+            }
+        }
+    }
+
+    /// Anything could come in, so this turns down whatever `encode` wouldn't have written.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0, player, match_number] => Some(Packet::Hello {
+                player: *player,
+                match_number: *match_number,
+            }),
+            [1, match_number, rest @ ..] if rest.len() >= 10 => {
+                let first_tick = u32::from_le_bytes(rest[0..4].try_into().ok()?);
+                let confirmed = u32::from_le_bytes(rest[4..8].try_into().ok()?);
+                let advantage = rest[8] as i8;
+                let frames = &rest[10..];
+                if frames.len() != rest[9] as usize {
+                    return None;
+                }
+                Some(Packet::Inputs {
+                    match_number: *match_number,
+                    first_tick,
+                    frames: frames
+                        .iter()
+                        .map(|byte| decode_frame(*byte))
+                        .collect::<Option<_>>()?,
+                    confirmed,
+                    advantage,
+                })
+            }
+            [2, match_number, rest @ ..] if rest.len() == 12 => Some(Packet::Checksum {
+                match_number: *match_number,
+                tick: u32::from_le_bytes(rest[0..4].try_into().ok()?),
+                value: u64::from_le_bytes(rest[4..12].try_into().ok()?),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Walk and chain in two bits each, then jump, slam and grapple.
+fn encode_frame(frame: InputFrame) -> u8 {
+    (frame.walk + 1) as u8
+        | (((frame.chain + 1) as u8) << 2)
+        | ((frame.jump as u8) << 4)
+        | ((frame.slam as u8) << 5)
+        | ((frame.grapple as u8) << 6)
+}
+
+fn decode_frame(byte: u8) -> Option<InputFrame> {
+    let (walk, chain) = (byte & 0b11, (byte >> 2) & 0b11);
+    if walk > 2 || chain > 2 || byte & (1 << 7) != 0 {
+        return None;
+    }
+    Some(InputFrame {
+        walk: walk as i8 - 1,
+        chain: chain as i8 - 1,
+        jump: byte & (1 << 4) != 0,
+        slam: byte & (1 << 5) != 0,
+        grapple: byte & (1 << 6) != 0,
+    })
+}
+
+/// The connection to the other client, and every input and snapshot a rollback might need.
+#[derive(Resource)]
+pub struct NetSession {
+    local: PlayerNumber,
+    transport: Transport,
+    /// Counts up every match, so stray packets of the last one are told apart.
+    match_number: u8,
+    /// The peer answered for this match.
+    connected: bool,
+    /// Next tick to simulate.
+    tick: u32,
+    /// Our input per tick, running [`INPUT_DELAY`] ahead of `tick`.
+    local_inputs: BTreeMap<u32, InputFrame>,
+    /// The peer's input per tick, as far as it arrived.
+    remote_inputs: BTreeMap<u32, InputFrame>,
+    /// Ticks of the peer's input we have, without gaps.
+    remote_confirmed: u32,
+    /// Ticks of our input the peer has.
+    peer_confirmed: u32,
+    /// One past the latest tick we have any input of the peer for.
+    remote_latest: u32,
+    /// What we guessed the peer did, for ticks simulated before their input arrived.
+    predicted: BTreeMap<u32, InputFrame>,
+    /// Oldest first.
+    snapshots: VecDeque<Snapshot>,
+    /// Oldest tick that was simulated with a wrong guess.
+    rollback_to: Option<u32>,
+    /// Ticks are being simulated again, with input we already have.
+    resimulating: bool,
+    /// How many ticks the peer said it is ahead of us.
+    remote_advantage: i32,
+    /// Ours, every [`CHECKSUM_INTERVAL`] ticks, until they can't change anymore and are sent.
+    checksums: BTreeMap<u32, u64>,
+    /// Sent to the peer, waiting for theirs.
+    sent_checksums: BTreeMap<u32, u64>,
+    remote_checksums: BTreeMap<u32, u64>,
+    /// Already complained about disagreeing with the peer.
+    desynced: bool,
+}
+
+impl NetSession {
+    fn new(local: PlayerNumber, transport: Transport) -> Self {
+        let mut session = Self {
+            local,
+            transport,
+            match_number: 0,
+            connected: false,
+            tick: 0,
+            local_inputs: BTreeMap::new(),
+            remote_inputs: BTreeMap::new(),
+            remote_confirmed: 0,
+            peer_confirmed: 0,
+            remote_latest: 0,
+            predicted: BTreeMap::new(),
+            snapshots: VecDeque::new(),
+            rollback_to: None,
+            resimulating: false,
+            remote_advantage: 0,
+            checksums: BTreeMap::new(),
+            sent_checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            desynced: false,
+        };
+        session.reset();
+        session
+    }
+
+    /// Back to tick 0, for the next match.
+    fn reset(&mut self) {
+        self.connected = false;
+        self.tick = 0;
+        // Nobody can act before their first input arrives.
+        self.local_inputs = (0..INPUT_DELAY)
+            .map(|tick| (tick, InputFrame::default()))
+            .collect();
+        self.remote_inputs.clear();
+        self.remote_confirmed = 0;
+        self.peer_confirmed = 0;
+        self.remote_latest = 0;
+        self.predicted.clear();
+        self.snapshots.clear();
+        self.rollback_to = None;
+        self.remote_advantage = 0;
+        self.checksums.clear();
+        self.sent_checksums.clear();
+        self.remote_checksums.clear();
+        self.desynced = false;
+    }
+
+    /// The peer's input for `tick`, or our guess at it.
+    fn remote_input(&self, tick: u32) -> InputFrame {
+        self.remote_inputs
+            .range(..=tick)
+            .next_back()
+            .map(|(_, frame)| *frame)
+            .unwrap_or_default()
+    }
+
+    fn local_advantage(&self) -> i32 {
+        self.tick as i32 - self.remote_latest as i32
+    }
+
+    fn store_snapshot(&mut self, snapshot: Snapshot) {
+        // Anything from this tick on is about to be simulated again.
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|last| last.tick >= snapshot.tick)
+        {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > MAX_PREDICTION as usize * 2 {
+            self.snapshots.pop_front();
+        }
+    }
+
+    fn send_hello(&mut self) {
+        let packet = Packet::Hello {
+            player: self.local.0 as u8,
+            match_number: self.match_number,
+        };
+        self.transport.send(packet.encode());
+    }
+
+    /// Sends every input the peer doesn't have yet.
+    fn send_inputs(&mut self) {
+        let frames: Vec<InputFrame> = self
+            .local_inputs
+            .range(self.peer_confirmed..)
+            .take(MAX_RESEND)
+            .map(|(_, frame)| *frame)
+            .collect();
+        let packet = Packet::Inputs {
+            match_number: self.match_number,
+            first_tick: self.peer_confirmed,
+            frames,
+            confirmed: self.remote_confirmed,
+            advantage: self.local_advantage().clamp(i8::MIN as i32, i8::MAX as i32) as i8,
+        };
+        self.transport.send(packet.encode());
+    }
+
+    fn receive_inputs(&mut self, first_tick: u32, frames: Vec<InputFrame>) {
+        for (tick, frame) in (first_tick..).zip(frames) {
+            if tick < self.remote_confirmed {
+                continue;
+            }
+            if self
+                .predicted
+                .get(&tick)
+                .is_some_and(|guess| *guess != frame)
+            {
+                self.rollback_to = Some(self.rollback_to.map_or(tick, |oldest| oldest.min(tick)));
+            }
+            self.remote_inputs.insert(tick, frame);
+            self.remote_latest = self.remote_latest.max(tick + 1);
+        }
+        while self.remote_inputs.contains_key(&self.remote_confirmed) {
+            self.remote_confirmed += 1;
+        }
+
+        // Keep what a rollback might simulate again, and the last confirmed input, which is the
+        // guess for what comes after.
+        let oldest = self
+            .remote_confirmed
+            .saturating_sub(1)
+            .min(self.tick.saturating_sub(MAX_PREDICTION * 2));
+        self.predicted = self.predicted.split_off(&self.remote_confirmed);
+        self.remote_inputs = self.remote_inputs.split_off(&oldest);
+    }
+
+    /// Sends our checksums for ticks that can't be rolled back anymore, and compares them with
+    /// the peer's.
+    fn compare_checksums(&mut self) {
+        let rollback_to = self.rollback_to;
+        let unsettled = self.checksums.split_off(&self.remote_confirmed);
+        let settled = std::mem::replace(&mut self.checksums, unsettled);
+        for (tick, value) in settled {
+            // Only settled once the coming rollback is done.
+            if rollback_to.is_some_and(|target| target <= tick) {
+                self.checksums.insert(tick, value);
+                continue;
+            }
+            let packet = Packet::Checksum {
+                match_number: self.match_number,
+                tick,
+                value,
+            };
+            self.transport.send(packet.encode());
+            self.sent_checksums.insert(tick, value);
+        }
+
+        let compared: Vec<u32> = self
+            .sent_checksums
+            .keys()
+            .filter(|tick| self.remote_checksums.contains_key(tick))
+            .copied()
+            .collect();
+        for tick in compared {
+            let ours = self.sent_checksums.remove(&tick);
+            let theirs = self.remote_checksums.remove(&tick);
+            if ours != theirs && !self.desynced {
+                error!("Out of sync with the peer since tick {tick} or before");
+                self.desynced = true;
+            }
+        }
+    }
+}
+
+/// Whether this is an online match, where the game can't be paused.
+pub fn online(session: Option<Res<NetSession>>) -> bool {
+    session.is_some()
+}
+
+/// Whether ticks are being simulated again after a rollback.
+pub fn resimulating(session: Option<Res<NetSession>>) -> bool {
+    session.is_some_and(|session| session.resimulating)
+}
+
+pub struct NetPlugin;
+
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        let mut bind = None;
+        let mut peer = None;
+        let mut player = 1;
+        let mut conditions = LinkConditions::default();
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let Some(value) = args.next() else {
+                break;
+            };
+            match arg.as_str() {
+                "--bind" => bind = parse::<SocketAddr>(&arg, &value),
+                "--peer" => peer = parse::<SocketAddr>(&arg, &value),
+                "--player" => player = parse::<usize>(&arg, &value).unwrap_or(player),
+                "--latency" => {
+                    conditions.latency = Duration::from_millis(parse(&arg, &value).unwrap_or(0));
+                }
+                "--jitter" => {
+                    conditions.jitter = Duration::from_millis(parse(&arg, &value).unwrap_or(0));
+                }
+                "--loss" => conditions.loss = parse::<f32>(&arg, &value).unwrap_or(0.0) / 100.0,
+                _ => {}
+            }
+        }
+
+        // Only online when asked to.
+        let (Some(bind), Some(peer)) = (bind, peer) else {
+            return;
+        };
+        if !(1..=2).contains(&player) {
+            error!("--player must be 1 or 2, not {player}");
+            return;
+        }
+        let transport = match Transport::bind(bind, peer, conditions) {
+            Ok(transport) => transport,
+            Err(e) => {
+                error!("Could not listen on {bind}: {e}");
+                return;
+            }
+        };
+        info!("Playing online as player {player}, with {peer}");
+
+        app.insert_resource(NetSession::new(PlayerNumber(player - 1), transport))
+            .add_systems(Startup, start_session)
+            .add_systems(OnEnter(GameState::Lobby), next_match)
+            .add_systems(OnExit(GameState::Playing), forget_despawned)
+            .add_systems(
+                RunFixedMainLoop,
+                (receive, sync_time, rollback)
+                    .chain()
+                    .in_set(RunFixedMainLoopSystem::BeforeFixedMainLoop)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (take_snapshot, forget_old_despawned, exchange_inputs)
+                    .chain()
+                    .after(gather_input)
+                    .before(controls)
+                    .in_set(TnuaUserControlsSystemSet)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedLast,
+                keep_checksum
+                    .after(take_checksum)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Option<T> {
+    let parsed = value.parse().ok();
+    if parsed.is_none() {
+        error!("Ignoring {flag} {value}");
+    }
+    parsed
+}
+
+/// Skips the lobby, with the local and the remote player.
+fn start_session(mut lobby: ResMut<Lobby>, mut next_state: ResMut<NextState<GameState>>) {
+    lobby.slots = (0..2)
+        .map(|number| LobbySlot {
+            // Both are overwritten from the session, the keyboard just drives the local one.
+            input: InputSource::Keyboard,
+            appearance: Appearance {
+                character: number % CHARACTERS.len(),
+                tint: number % TINTS.len(),
+            },
+            ready: true,
+        })
+        .collect();
+    next_state.set(GameState::Playing);
+}
+
+/// Straight into the next match when one is over.
+fn next_match(mut session: ResMut<NetSession>, mut next_state: ResMut<NextState<GameState>>) {
+    session.match_number = session.match_number.wrapping_add(1);
+    session.reset();
+    next_state.set(GameState::Playing);
+}
+
+fn receive(mut session: ResMut<NetSession>) {
+    let session = &mut *session;
+    for bytes in session.transport.poll() {
+        match Packet::decode(&bytes) {
+            Some(Packet::Hello {
+                player,
+                match_number,
+            }) if match_number == session.match_number => {
+                if player as usize == session.local.0 {
+                    warn!("Both clients are player {}", player + 1);
+                    continue;
+                }
+                if !session.connected {
+                    info!("Connected to player {}", player + 1);
+                    session.connected = true;
+                }
+                // They might not have heard from us yet.
+                session.send_hello();
+            }
+            Some(Packet::Inputs {
+                match_number,
+                first_tick,
+                frames,
+                confirmed,
+                advantage,
+            }) if match_number == session.match_number => {
+                session.connected = true;
+                session.peer_confirmed = session.peer_confirmed.max(confirmed);
+                session.remote_advantage = advantage as i32;
+                session.receive_inputs(first_tick, frames);
+            }
+            Some(Packet::Checksum {
+                match_number,
+                tick,
+                value,
+            }) if match_number == session.match_number => {
+                session.remote_checksums.insert(tick, value);
+            }
+            Some(_) => {}
+            None => warn!("Ignoring malformed packet"),
+        }
+    }
+
+    session.compare_checksums();
+    if session.connected {
+        // Covers for lost packets while nothing else is sent, as when waiting for the peer.
+        session.send_inputs();
+    } else {
+        session.send_hello();
+    }
+}
+
+/// Waits for the peer while too far ahead of them to guess their input, and slows down a little
+/// while ahead at all, so neither client has to roll back much more than the other.
+fn sync_time(session: Res<NetSession>, mut time: ResMut<Time<Virtual>>) {
+    let waiting = !session.connected || session.tick >= session.remote_confirmed + MAX_PREDICTION;
+    if waiting && !time.is_paused() {
+        time.pause();
+    } else if !waiting && time.is_paused() {
+        time.unpause();
+    }
+
+    // Both sides see the same latency, so half the difference is how far apart the clocks are.
+    let ahead = (session.local_advantage() - session.remote_advantage) / 2;
+    time.set_relative_speed(if ahead >= 1 { CATCH_UP_SPEED } else { 1.0 });
+}
+
+/// Goes back to the oldest mispredicted tick and simulates every tick since again.
+fn rollback(world: &mut World) {
+    let mut session = world.resource_mut::<NetSession>();
+    let Some(target) = session.rollback_to.take() else {
+        return;
+    };
+    let current = session.tick;
+    let Some(snapshot) = session
+        .snapshots
+        .iter()
+        .find(|snapshot| snapshot.tick == target)
+        .cloned()
+    else {
+        warn!("No snapshot of tick {target} to roll back to, clients may disagree from now on");
+        return;
+    };
+
+    snapshot.restore(world);
+    let mut session = world.resource_mut::<NetSession>();
+    session.tick = target;
+    session.resimulating = true;
+    world.resource_mut::<SimulationTick>().0 = target;
+
+    // The same as the fixed main loop does for every tick.
+    let frame_time = *world.resource::<Time>();
+    let fixed_time = world.resource::<Time<Fixed>>().as_generic();
+    *world.resource_mut::<Time>() = fixed_time;
+    for _ in target..current {
+        world.run_schedule(FixedMain);
+    }
+    *world.resource_mut::<Time>() = frame_time;
+
+    world.resource_mut::<NetSession>().resimulating = false;
+}
+
+/// Hands every player the input for this tick, ours from [`INPUT_DELAY`] ticks ago and the
+/// peer's as far as we know it.
+fn exchange_inputs(
+    mut session: ResMut<NetSession>,
+    phase: Option<Res<State<MatchPhase>>>,
+    players: Query<(&PlayerNumber, &mut InputFrame)>,
+) {
+    let session = &mut *session;
+    let tick = session.tick;
+
+    if !session.resimulating {
+        // `gather_input` only reads devices while fighting.
+        let local = if fighting(phase) {
+            players
+                .iter()
+                .find(|(number, _)| **number == session.local)
+                .map(|(_, frame)| *frame)
+                .unwrap_or_default()
+        } else {
+            InputFrame::default()
+        };
+        session.local_inputs.insert(tick + INPUT_DELAY, local);
+        session.send_inputs();
+    }
+
+    let remote = session.remote_input(tick);
+    if tick >= session.remote_confirmed {
+        session.predicted.insert(tick, remote);
+    }
+    let local = session.local_inputs.get(&tick).copied().unwrap_or_default();
+    for (number, mut frame) in players {
+        *frame = if *number == session.local {
+            local
+        } else {
+            remote
+        };
+    }
+
+    session.tick += 1;
+    // The peer won't ask for these again, and we never roll back past them.
+    let oldest = session
+        .peer_confirmed
+        .min(session.tick.saturating_sub(MAX_PREDICTION * 2));
+    session.local_inputs = session.local_inputs.split_off(&oldest);
+}
+
+/// Despawns for good whatever was [`Despawned`] before the oldest snapshot, no rollback can bring
+/// it back anymore.
+fn forget_old_despawned(
+    mut commands: Commands,
+    session: Res<NetSession>,
+    despawned: Query<(Entity, &Despawned), With<Disabled>>,
+) {
+    let Some(oldest) = session.snapshots.front() else {
+        return;
+    };
+    for (entity, Despawned(tick)) in despawned {
+        if *tick < oldest.tick {
+            commands.entity(entity).try_despawn();
+        }
+    }
+}
+
+/// The match is over, and so are rollbacks.
+fn forget_despawned(
+    mut commands: Commands,
+    despawned: Query<Entity, (With<Despawned>, With<Disabled>)>,
+) {
+    for entity in despawned {
+        commands.entity(entity).try_despawn();
+    }
+}
+
+fn keep_checksum(checksum: Res<StateChecksum>, mut session: ResMut<NetSession>) {
+    if checksum.tick.is_multiple_of(CHECKSUM_INTERVAL) {
+        session.checksums.insert(checksum.tick, checksum.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs() -> Packet {
+        Packet::Inputs {
+            match_number: 3,
+            first_tick: 70_000,
+            frames: vec![
+                InputFrame::default(),
+                InputFrame {
+                    walk: -1,
+                    jump: true,
+                    chain: 1,
+                    ..default()
+                },
+                InputFrame {
+                    walk: 1,
+                    slam: true,
+                    chain: -1,
+                    grapple: true,
+                    ..default()
+                },
+            ],
+            confirmed: 69_990,
+            advantage: -4,
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for packet in [
+            Packet::Hello {
+                player: 1,
+                match_number: 255,
+            },
+            inputs(),
+            Packet::Inputs {
+                match_number: 0,
+                first_tick: 0,
+                frames: Vec::new(),
+                confirmed: 0,
+                advantage: 0,
+            },
+            Packet::Checksum {
+                match_number: 7,
+                tick: 64,
+                value: 0xdead_beef_0bad_f00d,
+            },
+        ] {
+            assert_eq!(Packet::decode(&packet.encode()), Some(packet));
+        }
+    }
+
+    #[test]
+    fn turns_down_truncated_packets() {
+        let hello = Packet::Hello {
+            player: 0,
+            match_number: 1,
+        };
+        let checksum = Packet::Checksum {
+            match_number: 1,
+            tick: 128,
+            value: 42,
+        };
+        for packet in [hello, inputs(), checksum] {
+            let bytes = packet.encode();
+            for len in 0..bytes.len() {
+                assert_eq!(
+                    Packet::decode(&bytes[..len]),
+                    None,
+                    "{packet:?} cut to {len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn turns_down_oversized_packets() {
+        let mut bytes = inputs().encode();
+        bytes.push(0);
+        assert_eq!(Packet::decode(&bytes), None);
+
+        // More frames claimed than sent.
+        let mut bytes = inputs().encode();
+        bytes[11] = u8::MAX;
+        assert_eq!(Packet::decode(&bytes), None);
+
+        let mut bytes = Packet::Hello {
+            player: 0,
+            match_number: 1,
+        }
+        .encode();
+        bytes.push(0);
+        assert_eq!(Packet::decode(&bytes), None);
+
+        assert_eq!(Packet::decode(&[2; 64]), None);
+        assert_eq!(Packet::decode(&[3]), None);
+    }
+
+    #[test]
+    fn turns_down_frames_out_of_range() {
+        for bad in [0b11, 0b1100, 1 << 7] {
+            let mut bytes = inputs().encode();
+            *bytes.last_mut().unwrap() = bad;
+            assert_eq!(Packet::decode(&bytes), None, "{bad:#b}");
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Copies of the simulated state of the world at the start of a tick, to roll back to when a
+//! prediction of remote input turns out wrong.
+//!
+//! Everything the physics moves is kept: position and velocity of every body, the joints holding
+//! chains together and to tiles (solver state included), where each chain is held and who touches
+//! whom. So is the match phase and its timer, the match state of every player, status effects and
+//! slams included, what their Tnua controller was up to, see [`resume`](crate::resume), and which
+//! pickups are waiting to come back. Entities despawned since come back, see [`Despawned`], and
+//! those [`SpawnedInMatch`] since go away. That takes care of links added to, taken off or cut off
+//! a chain, and of grapples and grabs latching on and letting go, held and thrown bodies included.
+
+use std::time::Duration;
+
+use avian2d::prelude::*;
+use bevy::ecs::entity_disabling::Disabled;
+use bevy::prelude::*;
+use bevy_tnua::prelude::*;
+
+use crate::delete_after::{Clock, DeleteAt, Despawned, SpawnedInMatch};
+use crate::grab::{Grab, Held, Thrown};
+use crate::grapple::Grapple;
+use crate::impact::Slamming;
+use crate::pickup::Pickup;
+use crate::resume::{HeldActions, TnuaState};
+use crate::round::{Health, LastHitBy, MatchPhase, RoundTimer, Score, Stocks};
+use crate::status::{ActiveEffects, EffectsQuery};
+use crate::{Chain, ChainBase, ChainLink};
+
+#[derive(Clone)]
+struct BodyState {
+    entity: Entity,
+    position: Position,
+    rotation: Rotation,
+    linear_velocity: LinearVelocity,
+    angular_velocity: AngularVelocity,
+    transform: Transform,
+    held: Option<Held>,
+    thrown: Option<Thrown>,
+    /// Held bodies are heavier.
+    mass: Option<Mass>,
+    /// Thrown players report collisions.
+    collision_events: bool,
+}
+
+#[derive(Clone)]
+struct JointState {
+    entity: Entity,
+    joint: RevoluteJoint,
+    base: Option<ChainBase>,
+}
+
+#[derive(Clone)]
+struct PlayerState {
+    entity: Entity,
+    health: Health,
+    stocks: Stocks,
+    score: Score,
+    last_hit_by: Option<Entity>,
+    effects: ActiveEffects,
+    slamming: Option<Slamming>,
+    tnua: TnuaState,
+    chain: Chain,
+    grapple: Option<Grapple>,
+    grab: Option<Grab>,
+}
+
+#[derive(Clone)]
+pub struct Snapshot {
+    pub tick: u32,
+    bodies: Vec<BodyState>,
+    joints: Vec<JointState>,
+    /// Ropes of grapples, see [`Grapple`].
+    distance_joints: Vec<(Entity, DistanceJoint)>,
+    players: Vec<PlayerState>,
+    /// Cooldown of every pickup.
+    pickups: Vec<(Entity, Duration)>,
+    phase: MatchPhase,
+    timer: RoundTimer,
+    /// Everything [`SpawnedInMatch`] so far.
+    spawned: Vec<Entity>,
+    /// Lifetimes on [`Clock::Fixed`], like those of pieces cut off chains.
+    delete_at: Vec<(Entity, DeleteAt)>,
+    /// What avian knows about who touches whom, or contacts already there since would never
+    /// start again.
+    contacts: ContactGraph,
+    colliding: Vec<(Entity, CollidingEntities)>,
+}
+
+impl Snapshot {
+    /// Puts everything back the way it was, including entities [`Despawned`] since. Anything
+    /// [`SpawnedInMatch`] since is despawned.
+    pub fn restore(&self, world: &mut World) {
+        let despawned: Vec<Entity> = world
+            .query_filtered::<(Entity, &Despawned), With<Disabled>>()
+            .iter(world)
+            .filter(|(_, Despawned(tick))| *tick >= self.tick)
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in despawned {
+            world.entity_mut(entity).remove::<(Disabled, Despawned)>();
+        }
+
+        for body in &self.bodies {
+            let Ok(mut entity) = world.get_entity_mut(body.entity) else {
+                continue;
+            };
+            entity.insert((
+                body.position,
+                body.rotation,
+                body.linear_velocity,
+                body.angular_velocity,
+                body.transform,
+            ));
+            // Might have fallen asleep in the future we are undoing.
+            entity.remove::<Sleeping>();
+            match body.held {
+                Some(held) => entity.insert(held),
+                None => entity.remove::<Held>(),
+            };
+            match body.thrown {
+                Some(thrown) => entity.insert(thrown),
+                None => entity.remove::<Thrown>(),
+            };
+            match body.mass {
+                Some(mass) => entity.insert(mass),
+                None => entity.remove::<Mass>(),
+            };
+            if body.collision_events {
+                entity.insert(CollisionEventsEnabled);
+            } else {
+                entity.remove::<CollisionEventsEnabled>();
+            }
+        }
+
+        for joint in &self.joints {
+            let Ok(mut entity) = world.get_entity_mut(joint.entity) else {
+                continue;
+            };
+            entity.insert(joint.joint);
+            if let Some(base) = joint.base {
+                entity.insert(base);
+            }
+        }
+
+        for (entity, joint) in &self.distance_joints {
+            if let Ok(mut entity) = world.get_entity_mut(*entity) {
+                entity.insert(*joint);
+            }
+        }
+
+        for player in &self.players {
+            let Ok(mut entity) = world.get_entity_mut(player.entity) else {
+                continue;
+            };
+            entity.insert((player.health, player.stocks, player.score));
+            match player.last_hit_by {
+                Some(attacker) => entity.insert(LastHitBy(attacker)),
+                None => entity.remove::<LastHitBy>(),
+            };
+            player.effects.restore(&mut entity);
+            match player.slamming {
+                Some(slamming) => entity.insert(slamming),
+                None => entity.remove::<Slamming>(),
+            };
+            player.tnua.restore(&mut entity);
+            entity.insert(player.chain.clone());
+            match player.grapple {
+                Some(grapple) => entity.insert(grapple),
+                None => entity.remove::<Grapple>(),
+            };
+            match player.grab {
+                Some(grab) => entity.insert(grab),
+                None => entity.remove::<Grab>(),
+            };
+            // Back from pieces cut off since, before those go.
+            for link in &player.chain.links {
+                if let Ok(mut link) = world.get_entity_mut(*link) {
+                    link.remove::<ChildOf>().insert(ChainLink {
+                        player: player.entity,
+                    });
+                }
+            }
+        }
+
+        for (entity, cooldown) in &self.pickups {
+            if let Some(mut pickup) = world.get_mut::<Pickup>(*entity) {
+                pickup.cooldown = *cooldown;
+            }
+        }
+
+        for (entity, delete_at) in &self.delete_at {
+            if let Ok(mut entity) = world.get_entity_mut(*entity) {
+                entity.insert(delete_at.clone());
+            }
+        }
+
+        world.insert_resource(State::new(self.phase));
+        world.resource_mut::<NextState<MatchPhase>>().reset();
+        world.insert_resource(self.timer.clone());
+
+        let spawned: Vec<Entity> = world
+            .query_filtered::<Entity, With<SpawnedInMatch>>()
+            .iter(world)
+            .filter(|entity| !self.spawned.contains(entity))
+            .collect();
+        for entity in spawned {
+            // Might have gone along with its parent already.
+            if let Ok(entity) = world.get_entity_mut(entity) {
+                entity.despawn();
+            }
+        }
+
+        for (entity, colliding) in &self.colliding {
+            if let Ok(mut entity) = world.get_entity_mut(*entity) {
+                entity.insert(colliding.clone());
+            }
+        }
+        world.insert_resource(self.contacts.clone());
+    }
+}
+
+/// Keeps the state at the start of the current tick.
+pub fn take_snapshot(
+    mut session: ResMut<super::NetSession>,
+    bodies: Query<(
+        Entity,
+        &RigidBody,
+        &Position,
+        &Rotation,
+        &LinearVelocity,
+        &AngularVelocity,
+        &Transform,
+        (
+            Option<&Held>,
+            Option<&Thrown>,
+            Option<&Mass>,
+            Has<CollisionEventsEnabled>,
+        ),
+    )>,
+    joints: Query<(Entity, &RevoluteJoint, Option<&ChainBase>)>,
+    distance_joints: Query<(Entity, &DistanceJoint)>,
+    players: Query<(
+        Entity,
+        &Health,
+        &Stocks,
+        &Score,
+        Option<&LastHitBy>,
+        EffectsQuery,
+        Option<&Slamming>,
+        &TnuaController,
+        &HeldActions,
+        &Chain,
+        Option<&Grapple>,
+        Option<&Grab>,
+    )>,
+    pickups: Query<(Entity, &Pickup)>,
+    phase: Res<State<MatchPhase>>,
+    timer: Res<RoundTimer>,
+    spawned: Query<Entity, With<SpawnedInMatch>>,
+    delete_at: Query<(Entity, &DeleteAt)>,
+    contacts: Res<ContactGraph>,
+    colliding: Query<(Entity, &CollidingEntities)>,
+) {
+    let snapshot = Snapshot {
+        tick: session.tick,
+        bodies: bodies
+            .iter()
+            .filter(|(_, body, ..)| !body.is_static())
+            .map(
+                |(
+                    entity,
+                    _,
+                    position,
+                    rotation,
+                    linear_velocity,
+                    angular_velocity,
+                    transform,
+                    (held, thrown, mass, collision_events),
+                )| BodyState {
+                    entity,
+                    position: *position,
+                    rotation: *rotation,
+                    linear_velocity: *linear_velocity,
+                    angular_velocity: *angular_velocity,
+                    transform: *transform,
+                    held: held.copied(),
+                    thrown: thrown.copied(),
+                    mass: mass.copied(),
+                    collision_events,
+                },
+            )
+            .collect(),
+        joints: joints
+            .iter()
+            .map(|(entity, joint, base)| JointState {
+                entity,
+                joint: *joint,
+                base: base.copied(),
+            })
+            .collect(),
+        distance_joints: distance_joints
+            .iter()
+            .map(|(entity, joint)| (entity, *joint))
+            .collect(),
+        players: players
+            .iter()
+            .map(
+                |(
+                    entity,
+                    health,
+                    stocks,
+                    score,
+                    last_hit_by,
+                    effects,
+                    slamming,
+                    controller,
+                    held,
+                    chain,
+                    grapple,
+                    grab,
+                )| PlayerState {
+                    entity,
+                    health: *health,
+                    stocks: *stocks,
+                    score: *score,
+                    last_hit_by: last_hit_by.map(|LastHitBy(attacker)| *attacker),
+                    effects: ActiveEffects::new(effects),
+                    slamming: slamming.copied(),
+                    tnua: TnuaState::new(controller, held),
+                    chain: chain.clone(),
+                    grapple: grapple.copied(),
+                    grab: grab.copied(),
+                },
+            )
+            .collect(),
+        pickups: pickups
+            .iter()
+            .map(|(entity, pickup)| (entity, pickup.cooldown))
+            .collect(),
+        phase: *phase.get(),
+        timer: timer.clone(),
+        spawned: spawned.iter().collect(),
+        delete_at: delete_at
+            .iter()
+            .filter(|(_, delete_at)| delete_at.clock() == Clock::Fixed)
+            .map(|(entity, delete_at)| (entity, delete_at.clone()))
+            .collect(),
+        contacts: contacts.clone(),
+        colliding: colliding
+            .iter()
+            .map(|(entity, colliding)| (entity, colliding.clone()))
+            .collect(),
+    };
+    session.store_snapshot(snapshot);
+}
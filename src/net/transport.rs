@@ -0,0 +1,110 @@
+//! A UDP socket to the one peer of a session, which can pretend to be a worse connection than it
+//! is, so rollback can be tried out with two clients on one machine.
+
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use bevy::log::warn;
+use bevy::platform::time::Instant;
+
+/// Large enough for any packet we send, see [`super::Packet`].
+const MAX_PACKET: usize = 1024;
+
+/// How much worse than it is the connection should behave. Applied to what we send, so with both
+/// clients set the same the round trip gets twice the latency.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LinkConditions {
+    pub latency: Duration,
+    /// Random extra latency, up to this much. Can reorder packets.
+    pub jitter: Duration,
+    /// Chance from 0 to 1 of a packet getting lost.
+    pub loss: f32,
+}
+
+pub struct Transport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    conditions: LinkConditions,
+    /// Packets held back to simulate latency, with when they are due.
+    delayed: Vec<(Instant, Vec<u8>)>,
+    /// Xorshift state for loss and jitter.
+    rng: u32,
+}
+
+impl Transport {
+    pub fn bind(
+        local: SocketAddr,
+        peer: SocketAddr,
+        conditions: LinkConditions,
+    ) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peer,
+            conditions,
+            delayed: Vec::new(),
+            // Different per port, so two clients on one machine don't lose the same packets.
+            rng: 0x9e37_79b9 ^ local.port() as u32,
+        })
+    }
+
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1 << 24) as f32
+    }
+
+    pub fn send(&mut self, packet: Vec<u8>) {
+        if self.random() < self.conditions.loss {
+            return;
+        }
+        let delay = self.conditions.latency + self.conditions.jitter.mul_f32(self.random());
+        if delay.is_zero() {
+            self.send_now(&packet);
+        } else {
+            self.delayed.push((Instant::now() + delay, packet));
+        }
+    }
+
+    fn send_now(&self, packet: &[u8]) {
+        // Nothing listening yet, or a full buffer. Either way, UDP may lose packets anyway.
+        if let Err(e) = self.socket.send_to(packet, self.peer)
+            && e.kind() != ErrorKind::WouldBlock
+            && e.kind() != ErrorKind::ConnectionRefused
+        {
+            warn!("Could not send to {}: {e}", self.peer);
+        }
+    }
+
+    /// Sends whatever is due, and returns every packet that arrived from the peer.
+    pub fn poll(&mut self) -> Vec<Vec<u8>> {
+        let now = Instant::now();
+        let (due, waiting) = std::mem::take(&mut self.delayed)
+            .into_iter()
+            .partition(|(at, _)| *at <= now);
+        self.delayed = waiting;
+        for (_, packet) in due {
+            self.send_now(&packet);
+        }
+
+        let mut received = Vec::new();
+        let mut buffer = [0; MAX_PACKET];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) if from == self.peer => received.push(buffer[..len].to_vec()),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // Windows reports the peer not listening yet on the next receive.
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => {}
+                Err(e) => {
+                    warn!("Could not receive from {}: {e}", self.peer);
+                    break;
+                }
+            }
+        }
+        received
+    }
+}
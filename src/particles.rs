@@ -0,0 +1,355 @@
+//! Lightweight particles for effects like whip trails, sparks and dust.
+//!
+//! Particles are plain sprites moved on the CPU, no physics involved. Dead ones are hidden and
+//! reused for the next burst instead of despawned, and every particle dies on its own once its
+//! lifetime runs out.
+//!
+//! What each effect looks like is defined in `assets/particles.ron`, one [`EmitterDef`] per
+//! [`ParticleEffect`]:
+//!
+//! ```ron
+//! {
+//!     Sparks: (
+//!         count: 12,
+//!         lifetime: (0.2, 0.45),
+//!         speed: (200.0, 600.0),
+//!         ...
+//!     ),
+//! }
+//! ```
+
+use std::collections::HashMap;
+
+use avian2d::prelude::LinearVelocity;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use bevy_tnua::prelude::*;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::impact::{ImpactEvent, ImpactKind};
+use crate::status::FireTrail;
+use crate::{Chain, ChainLink, Player};
+
+/// Upper bound on particles alive at once. Bursts past it are cut short.
+const MAX_PARTICLES: usize = 2048;
+/// In front of the map and the players.
+const PARTICLE_Z: f32 = 5.0;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ParticleEffect {
+    /// Streaks behind a chain link moving fast.
+    WhipTrail,
+    /// A whip hitting the level.
+    Sparks,
+    /// A player landing.
+    Dust,
+    /// A player landing out of a slam.
+    Shockwave,
+    /// Flames along a chain with a [`FireTrail`].
+    Fire,
+}
+
+/// How an effect emits and moves its particles. Ranges are picked from uniformly per particle.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EmitterDef {
+    /// Particles per burst.
+    pub count: u32,
+    /// Seconds, particles never live longer than the upper end.
+    pub lifetime: (f32, f32),
+    /// Pixels per second, along the direction of the burst.
+    pub speed: (f32, f32),
+    /// Radians the particles fan out around the direction of the burst. `6.28` is a full circle.
+    pub spread: f32,
+    /// How much of the velocity of whatever emitted the burst the particles carry along.
+    #[serde(default)]
+    pub inherit_velocity: f32,
+    /// Pixels per second squared, pulling down.
+    #[serde(default)]
+    pub gravity: f32,
+    /// Fraction of their speed particles lose per second.
+    #[serde(default)]
+    pub drag: f32,
+    /// Size in pixels at birth and at death.
+    pub size: (f32, f32),
+    /// sRGBA at birth and at death.
+    pub color: ([f32; 4], [f32; 4]),
+}
+
+/// Every [`EmitterDef`], loaded from a `.ron` file.
+#[derive(Asset, TypePath, Deserialize)]
+#[serde(transparent)]
+pub struct EmitterLibrary(pub HashMap<ParticleEffect, EmitterDef>);
+
+#[derive(Default)]
+struct EmitterLibraryLoader;
+
+#[derive(Debug, Error)]
+pub enum EmitterLibraryError {
+    #[error("Could not read particle effects: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed particle effects: {0}")]
+    Ron(#[from] ron::de::SpannedError),
+}
+
+impl AssetLoader for EmitterLibraryLoader {
+    type Asset = EmitterLibrary;
+    type Settings = ();
+    type Error = EmitterLibraryError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Emit one burst of `effect` at `position`.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ParticleEvent {
+    pub effect: ParticleEffect,
+    pub position: Vec2,
+    /// Which way the burst goes, and how fast whatever emitted it was moving.
+    pub velocity: Vec2,
+}
+
+#[derive(Resource)]
+struct Emitters(Handle<EmitterLibrary>);
+
+impl FromWorld for Emitters {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<AssetServer>().load("particles.ron"))
+    }
+}
+
+/// Hidden particles, ready to be reused.
+#[derive(Resource, Default)]
+struct ParticlePool {
+    free: Vec<Entity>,
+    /// Alive and pooled.
+    total: usize,
+}
+
+/// Cheap xorshift, particles don't need anything better and it keeps us off `rand`.
+#[derive(Resource)]
+struct ParticleRng(u32);
+
+impl Default for ParticleRng {
+    fn default() -> Self {
+        Self(0x9e37_79b9)
+    }
+}
+
+impl ParticleRng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn range(&mut self, (min, max): (f32, f32)) -> f32 {
+        min + (max - min) * self.next()
+    }
+}
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    gravity: f32,
+    drag: f32,
+    size: (f32, f32),
+    color: (Color, Color),
+}
+
+/// Whether the player was in the air last frame, to spot landings.
+#[derive(Component, Default)]
+struct WasAirborne(bool);
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EmitterLibrary>()
+            .register_asset_loader(EmitterLibraryLoader)
+            .init_resource::<Emitters>()
+            .init_resource::<ParticlePool>()
+            .init_resource::<ParticleRng>()
+            .add_event::<ParticleEvent>()
+            .add_systems(
+                Update,
+                (
+                    (
+                        track_new_players,
+                        landing_dust,
+                        impact_particles,
+                        burning_chains,
+                    ),
+                    emit_particles,
+                    simulate_particles,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn track_new_players(mut commands: Commands, players: Query<Entity, Added<Player>>) {
+    for player in players {
+        commands.entity(player).insert(WasAirborne::default());
+    }
+}
+
+fn landing_dust(
+    mut particles: EventWriter<ParticleEvent>,
+    players: Query<(&TnuaController, &Transform, &mut WasAirborne)>,
+) {
+    for (controller, transform, mut was_airborne) in players {
+        let airborne = controller.is_airborne().unwrap_or(false);
+        if was_airborne.0 && !airborne {
+            particles.write(ParticleEvent {
+                effect: ParticleEffect::Dust,
+                // At the feet.
+                position: transform.translation.xy() - Vec2::new(0.0, 32.0),
+                velocity: Vec2::Y,
+            });
+        }
+        was_airborne.0 = airborne;
+    }
+}
+
+fn impact_particles(
+    mut impacts: EventReader<ImpactEvent>,
+    mut particles: EventWriter<ParticleEvent>,
+    bodies: Query<(), Or<(With<Player>, With<ChainLink>)>>,
+) {
+    for impact in impacts.read().filter(|impact| !impact.resimulated) {
+        let effect = match impact.kind {
+            ImpactKind::Whip | ImpactKind::Throw
+                if impact.target.is_some_and(|target| bodies.contains(target)) =>
+            {
+                continue;
+            }
+            ImpactKind::Whip | ImpactKind::Throw => ParticleEffect::Sparks,
+            ImpactKind::Slam => ParticleEffect::Shockwave,
+        };
+        particles.write(ParticleEvent {
+            effect,
+            position: impact.position,
+            velocity: Vec2::Y,
+        });
+    }
+}
+
+fn burning_chains(
+    mut particles: EventWriter<ParticleEvent>,
+    chains: Query<&Chain, With<FireTrail>>,
+    links: Query<(&Transform, &LinearVelocity), With<ChainLink>>,
+) {
+    for chain in chains {
+        for (transform, velocity) in links.iter_many(&chain.links) {
+            particles.write(ParticleEvent {
+                effect: ParticleEffect::Fire,
+                position: transform.translation.xy(),
+                velocity: velocity.0,
+            });
+        }
+    }
+}
+
+fn emit_particles(
+    mut commands: Commands,
+    mut events: EventReader<ParticleEvent>,
+    emitters: Res<Emitters>,
+    libraries: Res<Assets<EmitterLibrary>>,
+    mut pool: ResMut<ParticlePool>,
+    mut rng: ResMut<ParticleRng>,
+) {
+    let Some(EmitterLibrary(library)) = libraries.get(&emitters.0) else {
+        // Nothing to show until the effects are loaded.
+        events.clear();
+        return;
+    };
+
+    for event in events.read() {
+        let Some(def) = library.get(&event.effect) else {
+            warn_once!("No particle effect defined for {:?}", event.effect);
+            continue;
+        };
+        let direction = event.velocity.normalize_or(Vec2::Y);
+
+        for _ in 0..def.count {
+            let angle = (rng.next() - 0.5) * def.spread;
+            let velocity = Vec2::from_angle(angle).rotate(direction) * rng.range(def.speed)
+                + event.velocity * def.inherit_velocity;
+            let particle = Particle {
+                velocity,
+                age: 0.0,
+                lifetime: rng.range(def.lifetime),
+                gravity: def.gravity,
+                drag: def.drag,
+                size: def.size,
+                color: (srgba(def.color.0), srgba(def.color.1)),
+            };
+            let transform = Transform::from_translation(event.position.extend(PARTICLE_Z));
+
+            if let Some(entity) = pool.free.pop() {
+                commands
+                    .entity(entity)
+                    .insert((particle, transform, Visibility::Inherited));
+            } else if pool.total < MAX_PARTICLES {
+                pool.total += 1;
+                commands.spawn((particle, transform, Sprite::default()));
+            }
+        }
+    }
+}
+
+fn simulate_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pool: ResMut<ParticlePool>,
+    particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut particle, mut transform, mut sprite, mut visibility) in particles {
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            *visibility = Visibility::Hidden;
+            commands.entity(entity).remove::<Particle>();
+            pool.free.push(entity);
+            continue;
+        }
+
+        particle.velocity.y -= particle.gravity * delta;
+        let drag = particle.drag;
+        particle.velocity *= (1.0 - drag * delta).max(0.0);
+        transform.translation += (particle.velocity * delta).extend(0.0);
+
+        let t = particle.age / particle.lifetime;
+        let size = particle.size.0.lerp(particle.size.1, t);
+        sprite.custom_size = Some(Vec2::splat(size));
+        sprite.color = particle.color.0.mix(&particle.color.1, t);
+    }
+}
+
+fn srgba([red, green, blue, alpha]: [f32; 4]) -> Color {
+    Color::srgba(red, green, blue, alpha)
+}
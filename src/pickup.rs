@@ -0,0 +1,282 @@
+//! Power-ups lying around the map, placed in Tiled.
+//!
+//! Put a point object of class `pickup` on an object layer, with a string property `kind` naming
+//! one of the [`PickupKind`]s, like `heavy_tip`. A float property `respawn` sets how many seconds
+//! it takes to come back after it is collected, [`RESPAWN`] if it is left out. Players collect
+//! pickups by touching them, themselves or with their chain.
+
+use std::time::Duration;
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_tnua::TnuaNotPlatform;
+use bevy_tnua::prelude::TnuaUserControlsSystemSet;
+
+use crate::chain::ChainEditor;
+use crate::player::PlayerNumber;
+use crate::round::fighting;
+use crate::status::{DoubleJump, EffectTimer, FireTrail, HeavyTip, Invulnerable, apply_effect};
+use crate::tilemap::helpers::tiled::TiledMap;
+use crate::tilemap::objects_of_class;
+use crate::{Chain, ChainLink, GameState, LINK_MASS, Player};
+
+/// Seconds until a pickup comes back, unless the map says otherwise.
+pub const RESPAWN: f32 = 15.0;
+const RADIUS: f32 = 14.0;
+/// Links an [`PickupKind::ExtraLinks`] adds to the chain, until the player is knocked out.
+const EXTRA_LINKS: usize = 3;
+const HEAVY_TIP: Duration = Duration::from_secs(10);
+/// How much heavier the tip is with [`HeavyTip`].
+const HEAVY_TIP_MASS: f32 = 20.0;
+const FIRE_TRAIL: Duration = Duration::from_secs(8);
+const DOUBLE_JUMP: Duration = Duration::from_secs(12);
+const SHIELD: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PickupKind {
+    /// `extra_links`
+    ExtraLinks,
+    /// `heavy_tip`
+    HeavyTip,
+    /// `fire_trail`
+    FireTrail,
+    /// `double_jump`
+    DoubleJump,
+    /// `shield`
+    Shield,
+}
+
+impl PickupKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "extra_links" => Some(PickupKind::ExtraLinks),
+            "heavy_tip" => Some(PickupKind::HeavyTip),
+            "fire_trail" => Some(PickupKind::FireTrail),
+            "double_jump" => Some(PickupKind::DoubleJump),
+            "shield" => Some(PickupKind::Shield),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            PickupKind::ExtraLinks => "+",
+            PickupKind::HeavyTip => "@",
+            PickupKind::FireTrail => "~",
+            PickupKind::DoubleJump => "^^",
+            PickupKind::Shield => "O",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            PickupKind::ExtraLinks => Color::srgb(0.9, 0.85, 0.4),
+            PickupKind::HeavyTip => Color::srgb(0.7, 0.7, 0.75),
+            PickupKind::FireTrail => Color::srgb(1.0, 0.55, 0.1),
+            PickupKind::DoubleJump => Color::srgb(0.9, 0.6, 1.0),
+            PickupKind::Shield => Color::srgb(0.6, 0.9, 1.0),
+        }
+    }
+}
+
+/// A spot on the map a pickup comes back to after it is collected.
+#[derive(Component, Clone, Debug)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub respawn: Duration,
+    /// Time until it is back, zero while it can be collected.
+    pub cooldown: Duration,
+}
+
+impl Pickup {
+    pub fn available(&self) -> bool {
+        self.cooldown.is_zero()
+    }
+}
+
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (spawn_pickups, show_pickups))
+            .add_systems(OnEnter(GameState::Playing), reset_pickups)
+            .add_systems(
+                FixedUpdate,
+                (
+                    (respawn_pickups, collect_pickups.run_if(fighting)).chain(),
+                    weigh_tips,
+                )
+                    .before(TnuaUserControlsSystemSet)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Replaces every pickup with the ones in the map whenever it is (re)loaded.
+fn spawn_pickups(
+    mut commands: Commands,
+    mut map_events: EventReader<AssetEvent<TiledMap>>,
+    maps: Res<Assets<TiledMap>>,
+    pickups: Query<Entity, With<Pickup>>,
+) {
+    for event in map_events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        let Some(map) = maps.get(*id).map(|map| &map.map) else {
+            continue;
+        };
+        for pickup in &pickups {
+            commands.entity(pickup).despawn();
+        }
+
+        for (object, position) in objects_of_class(map, "pickup") {
+            let kind = match object.properties.get("kind") {
+                Some(tiled::PropertyValue::StringValue(name)) => PickupKind::from_name(name),
+                _ => None,
+            };
+            let Some(kind) = kind else {
+                warn!("Pickup {} has no valid kind", object.id());
+                continue;
+            };
+            let seconds = match object.properties.get("respawn") {
+                Some(tiled::PropertyValue::FloatValue(seconds)) => *seconds,
+                _ => RESPAWN,
+            };
+            let respawn = Duration::try_from_secs_f32(seconds).unwrap_or_else(|e| {
+                warn!(
+                    "Pickup {} has a bad respawn time {seconds}: {e}",
+                    object.id()
+                );
+                Duration::from_secs_f32(RESPAWN)
+            });
+
+            commands.spawn((
+                Pickup {
+                    kind,
+                    respawn,
+                    cooldown: Duration::ZERO,
+                },
+                Transform::from_translation(position.extend(2.0)),
+                Sprite {
+                    color: kind.color(),
+                    custom_size: Some(Vec2::splat(RADIUS * 2.0)),
+                    ..default()
+                },
+                RigidBody::Static,
+                Collider::circle(RADIUS),
+                Sensor,
+                CollidingEntities::default(),
+                // Or Tnua lets players stand on it.
+                TnuaNotPlatform,
+                children![(
+                    Text2d::new(kind.label()),
+                    TextFont::from_font_size(14.0),
+                    TextColor(Color::BLACK),
+                    Transform::from_xyz(0.0, 0.0, 1.0),
+                )],
+            ));
+        }
+    }
+}
+
+/// Every pickup is there at the start of a match.
+fn reset_pickups(pickups: Query<&mut Pickup>) {
+    for mut pickup in pickups {
+        pickup.cooldown = Duration::ZERO;
+    }
+}
+
+fn respawn_pickups(time: Res<Time>, pickups: Query<&mut Pickup>) {
+    for mut pickup in pickups {
+        if !pickup.available() {
+            pickup.cooldown = pickup.cooldown.saturating_sub(time.delta());
+        }
+    }
+}
+
+/// Gives every available pickup to the player touching it. If there are several, the lowest
+/// [`PlayerNumber`] gets it, so it is the same on every run.
+fn collect_pickups(
+    mut commands: Commands,
+    mut chains: ChainEditor,
+    pickups: Query<(&mut Pickup, &CollidingEntities)>,
+    players: Query<&PlayerNumber, With<Player>>,
+    links: Query<&ChainLink>,
+) {
+    for (mut pickup, touching) in pickups {
+        if !pickup.available() {
+            continue;
+        }
+        let collector = touching
+            .iter()
+            .map(|entity| links.get(*entity).map_or(*entity, |link| link.player))
+            .filter_map(|player| Some((player, *players.get(player).ok()?)))
+            .min_by_key(|(_, number)| number.0);
+        let Some((player, number)) = collector else {
+            continue;
+        };
+        info!("Player {} picked up {:?}", number.0 + 1, pickup.kind);
+        pickup.cooldown = pickup.respawn;
+
+        match pickup.kind {
+            PickupKind::ExtraLinks => {
+                chains.append(player, EXTRA_LINKS);
+            }
+            PickupKind::HeavyTip => {
+                commands
+                    .entity(player)
+                    .queue(apply_effect(HeavyTip(EffectTimer::new(HEAVY_TIP))));
+            }
+            PickupKind::FireTrail => {
+                commands
+                    .entity(player)
+                    .queue(apply_effect(FireTrail(EffectTimer::new(FIRE_TRAIL))));
+            }
+            PickupKind::DoubleJump => {
+                commands
+                    .entity(player)
+                    .queue(apply_effect(DoubleJump::new(EffectTimer::new(DOUBLE_JUMP))));
+            }
+            PickupKind::Shield => {
+                commands
+                    .entity(player)
+                    .queue(apply_effect(Invulnerable(EffectTimer::new(SHIELD))));
+            }
+        }
+    }
+}
+
+/// Makes the tip of every chain heavy while its player has [`HeavyTip`], and every other link
+/// normal again, which covers the old tip after the chain grew.
+fn weigh_tips(
+    players: Query<(&Chain, Has<HeavyTip>), With<Player>>,
+    mut links: Query<&mut Mass, With<ChainLink>>,
+) {
+    for (chain, heavy) in players {
+        for (i, link) in chain.links.iter().enumerate() {
+            let Ok(mut mass) = links.get_mut(*link) else {
+                continue;
+            };
+            let tip = i + 1 == chain.links.len();
+            let wanted = if tip && heavy {
+                LINK_MASS * HEAVY_TIP_MASS
+            } else {
+                LINK_MASS
+            };
+            if mass.0 != wanted {
+                mass.0 = wanted;
+            }
+        }
+    }
+}
+
+fn show_pickups(pickups: Query<(&Pickup, &mut Visibility), Changed<Pickup>>) {
+    for (pickup, mut visibility) in pickups {
+        *visibility = if pickup.available() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
@@ -0,0 +1,131 @@
+use bevy::input::gamepad::GamepadEvent;
+use bevy::prelude::*;
+
+use crate::delete_after::Despawner;
+use crate::lobby::Lobby;
+use crate::{Chain, GameState, Player};
+
+pub const CHARACTERS: [&str; 3] = ["character2.png", "character.png", "character_feet.png"];
+
+pub const TINTS: [Color; 6] = [
+    Color::WHITE,
+    Color::srgb(1.0, 0.45, 0.45),
+    Color::srgb(0.45, 0.65, 1.0),
+    Color::srgb(0.5, 1.0, 0.5),
+    Color::srgb(1.0, 0.9, 0.4),
+    Color::srgb(0.85, 0.5, 1.0),
+];
+
+/// Which player this is, counting from zero in the order they joined the lobby.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlayerNumber(pub usize);
+
+/// The character sprite and tint a player picked in the lobby, as indices into [`CHARACTERS`]
+/// and [`TINTS`].
+#[derive(Component, Clone, Copy)]
+pub struct Appearance {
+    pub character: usize,
+    pub tint: usize,
+}
+
+impl Appearance {
+    pub fn image(self) -> &'static str {
+        CHARACTERS[self.character]
+    }
+
+    /// The sprite sheet and animations of the character, see [`animation`](crate::animation).
+    pub fn animations(self) -> String {
+        let name = self.image().trim_end_matches(".png");
+        format!("animations/{name}.anim.ron")
+    }
+
+    pub fn color(self) -> Color {
+        TINTS[self.tint]
+    }
+}
+
+/// The device a player is controlled with.
+///
+/// Gamepad entities are kept alive by Bevy when a pad disconnects and reused when the same pad
+/// reconnects, so the entity is enough to find the player again.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputSource {
+    Keyboard,
+    Gamepad(Entity),
+    /// Played back from a recording, see [`replay`](crate::replay). No device can take it over.
+    Replay,
+}
+
+/// The player's gamepad is disconnected. The player stays in the world, but ignores input until
+/// the same pad comes back. New pads join through the lobby instead.
+#[derive(Component)]
+pub struct Absent;
+
+pub const ABSENT_ALPHA: f32 = 0.4;
+
+pub fn gamepad_connections(
+    mut commands: Commands,
+    mut event_reader: EventReader<GamepadEvent>,
+    mut players: Query<(Entity, &InputSource, &mut Sprite), With<Player>>,
+) {
+    for event in event_reader.read() {
+        let GamepadEvent::Connection(connection) = event else {
+            continue;
+        };
+        let gamepad = connection.gamepad;
+
+        let owner = players
+            .iter_mut()
+            .find(|(_, source, _)| **source == InputSource::Gamepad(gamepad));
+
+        // The chain fades along with the player, see `chain_rope`.
+        if let Some((player, _, mut sprite)) = owner {
+            let alpha = if connection.connected() {
+                info!("Gamepad {gamepad} reconnected, {player} is back");
+                commands.entity(player).remove::<Absent>();
+                1.0
+            } else {
+                info!("Gamepad {gamepad} disconnected, {player} is absent");
+                commands.entity(player).insert(Absent);
+                ABSENT_ALPHA
+            };
+            sprite.color.set_alpha(alpha);
+        }
+    }
+}
+
+/// Lets a player drop out of the game, taking their whole chain with them. Once everyone is gone
+/// we are back in the lobby.
+pub fn leave(
+    mut despawner: Despawner,
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<GameState>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    players: Query<(Entity, &InputSource, &Chain), (With<Player>, Without<Absent>)>,
+) {
+    for (player, source, chain) in players {
+        let wants_to_leave = match source {
+            InputSource::Keyboard => keyboard.just_pressed(KeyCode::Backspace),
+            InputSource::Gamepad(gamepad) => gamepads
+                .get(*gamepad)
+                .is_ok_and(|gamepad| gamepad.just_pressed(GamepadButton::Select)),
+            InputSource::Replay => false,
+        };
+
+        if wants_to_leave {
+            info!("{player} left the game");
+            lobby.leave(*source);
+            despawn_player(&mut despawner, player, chain);
+        }
+    }
+
+    if lobby.slots.is_empty() {
+        next_state.set(GameState::Lobby);
+    }
+}
+
+pub fn despawn_player(despawner: &mut Despawner, player: Entity, chain: &Chain) {
+    // The links are not children of the player, but the joints go along with them.
+    despawner.despawn_all(chain.links.iter().copied().chain([player]));
+}
@@ -0,0 +1,307 @@
+//! Recording and replaying the per-tick [`InputFrame`]s of every player.
+//!
+//! Start the game with `--record <file>` to record every match, or with `--replay <file>` to skip
+//! the lobby and play a recording back. A replay spawns the same players and feeds the recorded
+//! frames to them instead of reading devices, so the same chain physics play out again.
+//!
+//! The format is plain text, one line per player per tick:
+//!
+//! ```text
+//! chainwhips-replay 1
+//! player <number> <character> <tint>
+//! ...
+//! <tick> <player number> <walk> <jump> <slam> <chain> <grapple>
+//! ```
+//!
+//! Recordings from before grappling leave out `<grapple>`, it is never held in those.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy_tnua::prelude::TnuaUserControlsSystemSet;
+use thiserror::Error;
+
+use crate::GameState;
+use crate::input::{InputFrame, controls, gather_input};
+use crate::lobby::{Lobby, LobbySlot};
+use crate::player::{Appearance, CHARACTERS, InputSource, PlayerNumber, TINTS};
+
+const HEADER: &str = "chainwhips-replay 1";
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Could not access replay file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed replay, line {line}: {reason}")]
+    Parse { line: usize, reason: String },
+}
+
+/// Writes the input of every player to a file while playing.
+#[derive(Resource)]
+pub struct InputRecorder {
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+    tick: u64,
+}
+
+/// Feeds recorded input to the players instead of their devices.
+#[derive(Resource)]
+pub struct InputReplay {
+    /// In the order they joined, which is also their [`PlayerNumber`].
+    players: Vec<Appearance>,
+    /// Indexed by tick.
+    frames: Vec<Vec<(PlayerNumber, InputFrame)>>,
+    tick: usize,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut replay = InputReplay {
+            players: Vec::new(),
+            frames: Vec::new(),
+            tick: 0,
+        };
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let parse_error = |reason: &str| ReplayError::Parse {
+                line: index + 1,
+                reason: reason.to_string(),
+            };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            if index == 0 {
+                if line != HEADER {
+                    return Err(parse_error("not a chainwhips replay"));
+                }
+                continue;
+            }
+
+            let numbers = |fields: &[&str]| {
+                fields
+                    .iter()
+                    .map(|field| field.parse::<i64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| parse_error(&e.to_string()))
+            };
+
+            match fields.as_slice() {
+                [] => {}
+                ["player", rest @ ..] => {
+                    let [number, character, tint] = numbers(rest)?[..] else {
+                        return Err(parse_error("expected player <number> <character> <tint>"));
+                    };
+                    if number != replay.players.len() as i64 {
+                        return Err(parse_error("players out of order"));
+                    }
+                    let (Some(character), Some(tint)) = (
+                        index_into(character, CHARACTERS.len()),
+                        index_into(tint, TINTS.len()),
+                    ) else {
+                        return Err(parse_error("no such character or tint"));
+                    };
+                    replay.players.push(Appearance { character, tint });
+                }
+                fields => {
+                    let values = numbers(fields)?;
+                    let (tick, player, walk, jump, slam, chain, grapple) = match values[..] {
+                        [tick, player, walk, jump, slam, chain] => {
+                            (tick, player, walk, jump, slam, chain, 0)
+                        }
+                        [tick, player, walk, jump, slam, chain, grapple] => {
+                            (tick, player, walk, jump, slam, chain, grapple)
+                        }
+                        _ => {
+                            return Err(parse_error(
+                                "expected <tick> <player> <walk> <jump> <slam> <chain> <grapple>",
+                            ));
+                        }
+                    };
+                    // Recorded one tick after the other, so anything else is not a recording.
+                    let last = replay.frames.len().saturating_sub(1);
+                    let Some(tick) =
+                        index_into(tick, replay.frames.len() + 1).filter(|tick| *tick >= last)
+                    else {
+                        return Err(parse_error("tick out of order"));
+                    };
+                    let Some(player) = index_into(player, replay.players.len()) else {
+                        return Err(parse_error("no such player"));
+                    };
+                    if ![walk, chain].iter().all(|axis| (-1..=1).contains(axis))
+                        || ![jump, slam, grapple]
+                            .iter()
+                            .all(|button| (0..=1).contains(button))
+                    {
+                        return Err(parse_error("input out of range"));
+                    }
+                    if replay.frames.len() == tick {
+                        replay.frames.push(Vec::new());
+                    }
+                    replay.frames[tick].push((
+                        PlayerNumber(player),
+                        InputFrame {
+                            walk: walk as i8,
+                            jump: jump != 0,
+                            slam: slam != 0,
+                            chain: chain as i8,
+                            grapple: grapple != 0,
+                        },
+                    ));
+                }
+            }
+        }
+
+        Ok(replay)
+    }
+}
+
+/// `value` as an index into something `len` long, if it is one.
+fn index_into(value: i64, len: usize) -> Option<usize> {
+    usize::try_from(value).ok().filter(|index| *index < len)
+}
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), args.next()) {
+                ("--record", Some(path)) => {
+                    app.insert_resource(InputRecorder {
+                        path: path.into(),
+                        file: None,
+                        tick: 0,
+                    });
+                }
+                ("--replay", Some(path)) => match InputReplay::load(Path::new(&path)) {
+                    Ok(replay) => {
+                        app.insert_resource(replay);
+                    }
+                    Err(e) => error!("Could not load replay {path}: {e}"),
+                },
+                _ => {}
+            }
+        }
+
+        app.add_systems(Startup, start_replay.run_if(resource_exists::<InputReplay>))
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (
+                    start_recording.run_if(resource_exists::<InputRecorder>),
+                    rewind_replay.run_if(resource_exists::<InputReplay>),
+                ),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    replay_inputs
+                        .run_if(resource_exists::<InputReplay>)
+                        .after(gather_input)
+                        .before(controls),
+                    record_inputs
+                        .run_if(resource_exists::<InputRecorder>)
+                        .after(replay_inputs)
+                        .before(controls),
+                )
+                    .in_set(TnuaUserControlsSystemSet)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Skips the lobby, with the players the replay was recorded with.
+fn start_replay(
+    replay: Res<InputReplay>,
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    lobby.slots = replay
+        .players
+        .iter()
+        .map(|appearance| LobbySlot {
+            input: InputSource::Replay,
+            appearance: *appearance,
+            ready: true,
+        })
+        .collect();
+    next_state.set(GameState::Playing);
+}
+
+fn rewind_replay(mut replay: ResMut<InputReplay>) {
+    replay.tick = 0;
+}
+
+fn start_recording(mut recorder: ResMut<InputRecorder>, lobby: Res<Lobby>) {
+    let recorder = &mut *recorder;
+    let result = File::create(&recorder.path).and_then(|file| {
+        let mut file = BufWriter::new(file);
+        writeln!(file, "{HEADER}")?;
+        for (number, slot) in lobby.slots.iter().enumerate() {
+            let Appearance { character, tint } = slot.appearance;
+            writeln!(file, "player {number} {character} {tint}")?;
+        }
+        Ok(file)
+    });
+
+    match result {
+        Ok(file) => {
+            info!("Recording input to {}", recorder.path.display());
+            recorder.file = Some(file);
+            recorder.tick = 0;
+        }
+        Err(e) => error!("Could not record to {}: {e}", recorder.path.display()),
+    }
+}
+
+fn record_inputs(
+    mut recorder: ResMut<InputRecorder>,
+    players: Query<(&PlayerNumber, &InputFrame)>,
+) {
+    let recorder = &mut *recorder;
+    let Some(file) = &mut recorder.file else {
+        return;
+    };
+
+    let tick = recorder.tick;
+    let result = players.iter().try_for_each(|(number, frame)| {
+        writeln!(
+            file,
+            "{tick} {} {} {} {} {} {}",
+            number.0,
+            frame.walk,
+            frame.jump as u8,
+            frame.slam as u8,
+            frame.chain,
+            frame.grapple as u8
+        )
+    });
+    recorder.tick += 1;
+
+    if let Err(e) = result {
+        error!("Stopped recording to {}: {e}", recorder.path.display());
+        recorder.file = None;
+    }
+}
+
+fn replay_inputs(
+    mut replay: ResMut<InputReplay>,
+    players: Query<(&PlayerNumber, &mut InputFrame)>,
+) {
+    let tick = replay.tick;
+    let frames = replay.frames.get(tick);
+    if tick == replay.frames.len() {
+        info!("Replay finished after {tick} ticks");
+    }
+
+    for (number, mut frame) in players {
+        *frame = frames
+            .and_then(|frames| frames.iter().find(|(player, _)| player == number))
+            .map(|(_, frame)| *frame)
+            .unwrap_or_default();
+    }
+    replay.tick += 1;
+}
@@ -0,0 +1,332 @@
+//! Putting a Tnua controller back the way it was, for [rollbacks](crate::net).
+//!
+//! Tnua keeps what a controller is up to to itself, so it can't be copied. It is rebuilt instead:
+//! a fresh controller gets the state of the walk basis back, and jump and slam, which always go
+//! through [`Resumable`], pick up where they were. [`HeldActions`] remembers how long they have
+//! been held, which Tnua knows but doesn't tell, so a held button isn't taken for a fresh press.
+//! It also keeps a jump pressed while the last one is still going, which Tnua would keep next to
+//! the running one where it can't be put back, see [`HeldActions::jump`].
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::Stopwatch;
+use bevy_tnua::builtins::{
+    TnuaBuiltinDash, TnuaBuiltinDashState, TnuaBuiltinJumpState, TnuaBuiltinWalkState,
+};
+use bevy_tnua::math::Float;
+use bevy_tnua::prelude::*;
+use bevy_tnua::{
+    TnuaActionContext, TnuaActionInitiationDirective, TnuaActionLifecycleDirective,
+    TnuaActionLifecycleStatus, TnuaMotor,
+};
+
+use crate::input::Movement;
+
+/// Runs `action`, or picks up where a rolled back one was.
+pub struct Resumable<A: TnuaAction> {
+    action: A,
+    resume: Option<Resume<A::State>>,
+}
+
+impl<A: TnuaAction> Resumable<A> {
+    pub fn new(action: A) -> Self {
+        Self {
+            action,
+            resume: None,
+        }
+    }
+}
+
+/// What an action was up to at the tick rolled back to.
+#[derive(Clone)]
+enum Resume<S> {
+    /// Going on. `fed` is whether it still is this tick.
+    Running { state: S, fed: bool },
+    /// Held, but done or never allowed to start. It takes a fresh press to go again.
+    Held,
+    /// Held this long without starting yet, like a jump waiting to land.
+    Buffered(Duration),
+}
+
+impl<A: TnuaAction> TnuaAction for Resumable<A>
+where
+    A::State: Clone,
+{
+    const NAME: &'static str = A::NAME;
+    type State = A::State;
+    const VIOLATES_COYOTE_TIME: bool = A::VIOLATES_COYOTE_TIME;
+
+    fn apply(
+        &self,
+        state: &mut Self::State,
+        ctx: TnuaActionContext,
+        lifecycle_status: TnuaActionLifecycleStatus,
+        motor: &mut TnuaMotor,
+    ) -> TnuaActionLifecycleDirective {
+        let lifecycle_status = match &self.resume {
+            // Only just started for Tnua, not for us.
+            Some(Resume::Running {
+                state: resumed,
+                fed,
+            }) if lifecycle_status.just_started() => {
+                *state = resumed.clone();
+                if *fed {
+                    TnuaActionLifecycleStatus::StillFed
+                } else {
+                    TnuaActionLifecycleStatus::NoLongerFed
+                }
+            }
+            _ => lifecycle_status,
+        };
+        self.action.apply(state, ctx, lifecycle_status, motor)
+    }
+
+    fn proximity_sensor_cast_range(&self) -> Float {
+        self.action.proximity_sensor_cast_range()
+    }
+
+    fn initiation_decision(
+        &self,
+        ctx: TnuaActionContext,
+        being_fed_for: &Stopwatch,
+    ) -> TnuaActionInitiationDirective {
+        match &self.resume {
+            Some(Resume::Running { .. }) => TnuaActionInitiationDirective::Allow,
+            Some(Resume::Held) => TnuaActionInitiationDirective::Reject,
+            Some(Resume::Buffered(held_for)) => {
+                let mut being_fed_for = being_fed_for.clone();
+                being_fed_for.set_elapsed(being_fed_for.elapsed() + *held_for);
+                self.action.initiation_decision(ctx, &being_fed_for)
+            }
+            None => self.action.initiation_decision(ctx, being_fed_for),
+        }
+    }
+
+    fn target_entity(&self, state: &Self::State) -> Option<Entity> {
+        self.action.target_entity(state)
+    }
+}
+
+/// How long jump and slam have been fed to the controller in a row, and whether that got them
+/// going.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct HeldActions {
+    jump: Option<Hold>,
+    slam: Option<Hold>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Hold {
+    held_for: Duration,
+    started: bool,
+    /// Waited for the last jump to be over for longer than the input buffer.
+    expired: bool,
+}
+
+impl HeldActions {
+    /// Call every tick before feeding the controller, with whether jump and slam are fed.
+    pub fn update(&mut self, controller: &TnuaController, jump: bool, slam: bool, delta: Duration) {
+        update_hold(
+            &mut self.jump,
+            controller,
+            TnuaBuiltinJump::NAME,
+            jump,
+            delta,
+        );
+        update_hold(
+            &mut self.slam,
+            controller,
+            TnuaBuiltinDash::NAME,
+            slam,
+            delta,
+        );
+    }
+
+    /// The jump to feed the controller this tick, if any, with `jumping` whether the last one is
+    /// still going, see [`jumping`]. A fresh press in the meantime waits here and is fed once that
+    /// jump is over, unless it has waited longer than the `input_buffer_time` of `jump` by then.
+    pub fn jump(
+        &mut self,
+        jump: &TnuaBuiltinJump,
+        jumping: bool,
+        delta: Duration,
+    ) -> Option<Resumable<TnuaBuiltinJump>> {
+        let hold = self.jump.as_mut()?;
+        if hold.expired {
+            return None;
+        }
+        if hold.started {
+            return Some(Resumable::new(jump.clone()));
+        }
+        // How long Tnua would have been fed it, had it been fed right away.
+        let fed_for = hold.held_for.saturating_sub(delta);
+        if jumping {
+            hold.expired = fed_for.as_secs_f32() >= jump.input_buffer_time;
+            return None;
+        }
+        Some(Resumable {
+            action: jump.clone(),
+            resume: Some(Resume::Buffered(fed_for)),
+        })
+    }
+}
+
+/// Whether a jump is going, or about to be resumed.
+pub fn jumping(controller: &TnuaController, resume: Option<&ResumeTnua>) -> bool {
+    match resume {
+        Some(ResumeTnua(state)) => matches!(state.jump, Some(Resume::Running { .. })),
+        None => controller.action_name() == Some(TnuaBuiltinJump::NAME),
+    }
+}
+
+fn update_hold(
+    hold: &mut Option<Hold>,
+    controller: &TnuaController,
+    name: &str,
+    fed: bool,
+    delta: Duration,
+) {
+    let started = started(controller, name);
+    *hold = fed.then(|| {
+        let hold = hold.unwrap_or(Hold {
+            held_for: Duration::ZERO,
+            started: false,
+            expired: false,
+        });
+        Hold {
+            held_for: hold.held_for + delta,
+            started: hold.started || started,
+            ..hold
+        }
+    });
+}
+
+/// Whether the controller started the action on the last tick.
+fn started(controller: &TnuaController, name: &str) -> bool {
+    controller.action_flow_status().just_starting() == Some(name)
+}
+
+/// Everything needed to rebuild the controller of a player, see [`ResumeTnua`].
+#[derive(Clone)]
+pub struct TnuaState {
+    walk: Option<TnuaBuiltinWalkState>,
+    jump: Option<Resume<TnuaBuiltinJumpState>>,
+    slam: Option<Resume<TnuaBuiltinDashState>>,
+    held: HeldActions,
+}
+
+impl TnuaState {
+    pub fn new(controller: &TnuaController, held: &HeldActions) -> Self {
+        let mut held = *held;
+        // Only noticed at the start of the next tick otherwise.
+        for (hold, name) in [
+            (&mut held.jump, TnuaBuiltinJump::NAME),
+            (&mut held.slam, TnuaBuiltinDash::NAME),
+        ] {
+            if let Some(hold) = hold {
+                hold.started |= started(controller, name);
+            }
+        }
+
+        Self {
+            walk: controller
+                .concrete_basis::<TnuaBuiltinWalk>()
+                .map(|(_, state)| state.clone()),
+            jump: resume::<TnuaBuiltinJump>(controller, held.jump),
+            slam: resume::<TnuaBuiltinDash>(controller, held.slam),
+            held,
+        }
+    }
+
+    /// Swaps the controller for a fresh one, which picks up from here on the next tick.
+    pub fn restore(&self, entity: &mut EntityWorldMut) {
+        entity.insert((
+            TnuaController::default(),
+            self.held,
+            ResumeTnua(self.clone()),
+        ));
+    }
+}
+
+fn resume<A: TnuaAction>(
+    controller: &TnuaController,
+    hold: Option<Hold>,
+) -> Option<Resume<A::State>>
+where
+    A::State: Clone,
+{
+    if let Some((_, state)) = controller.concrete_action::<Resumable<A>>() {
+        return Some(Resume::Running {
+            state: state.clone(),
+            // Up to whoever feeds it.
+            fed: false,
+        });
+    }
+    let hold = hold?;
+    Some(if hold.started || hold.expired {
+        Resume::Held
+    } else {
+        Resume::Buffered(hold.held_for)
+    })
+}
+
+/// The controller was swapped for a fresh one by a rollback, and gets fed what the old one was
+/// doing on the next tick, see [`ResumeTnua::feed`].
+#[derive(Component)]
+pub struct ResumeTnua(TnuaState);
+
+impl ResumeTnua {
+    /// Feeds the controller before anything else this tick. `jump` and `slam` are whether they
+    /// are fed after.
+    pub fn feed(
+        &self,
+        controller: &mut TnuaController,
+        movement: &Movement,
+        jump: bool,
+        slam: bool,
+    ) {
+        let state = &self.0;
+        // Fed again with where it's headed later on, which keeps the state.
+        controller.basis(movement.walk.clone());
+        if let (Some((_, walk)), Some(resumed)) = (
+            controller.concrete_basis_mut::<TnuaBuiltinWalk>(),
+            &state.walk,
+        ) {
+            *walk = resumed.clone();
+        }
+
+        // Only one action gets to go next tick, and it should be the one already running.
+        if matches!(state.jump, Some(Resume::Running { .. })) {
+            feed(controller, &movement.slam, &state.slam, slam);
+            feed(controller, &movement.jump, &state.jump, jump);
+        } else {
+            feed(controller, &movement.jump, &state.jump, jump);
+            feed(controller, &movement.slam, &state.slam, slam);
+        }
+    }
+}
+
+fn feed<A: TnuaAction + Clone>(
+    controller: &mut TnuaController,
+    action: &A,
+    resume: &Option<Resume<A::State>>,
+    fed: bool,
+) where
+    A::State: Clone,
+{
+    let resume = match resume {
+        Some(Resume::Running { state, .. }) => Resume::Running {
+            state: state.clone(),
+            fed,
+        },
+        // Let go of since, a fresh press starts it again.
+        Some(Resume::Held) if !fed => return,
+        Some(resume) => resume.clone(),
+        None => return,
+    };
+    controller.action(Resumable {
+        action: action.clone(),
+        resume: Some(resume),
+    });
+}
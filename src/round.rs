@@ -0,0 +1,388 @@
+//! The flow of a match once everyone is in: a countdown, the fight itself, and sudden death when
+//! time runs out. Also keeps track of everyone's health, stocks and score.
+
+use std::time::Duration;
+
+use avian2d::prelude::*;
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+
+use crate::chain::ChainEditor;
+use crate::delete_after::{DeleteAt, Despawner, SpawnedInMatch};
+use crate::impact::{ImpactEvent, ImpactKind, ImpactSystems};
+use crate::lobby::Lobby;
+use crate::player::{PlayerNumber, despawn_player};
+use crate::status::{
+    ChainLocked, EffectTimer, FireTrail, HeavyTip, Invulnerable, Slowed, SpeedBoost, Stunned,
+    apply_effect,
+};
+use crate::{CHAIN_LINK_COUNT, Chain, GameState, Player, spawn_point};
+
+const COUNTDOWN: Duration = Duration::from_secs(3);
+const FIGHT: Duration = Duration::from_secs(120);
+/// How long the winner is shown before going back to the lobby.
+const FINISHED: Duration = Duration::from_secs(4);
+/// Lives every player starts a match with.
+pub const STOCKS: u32 = 3;
+/// Whip impact speed that takes away one point of health.
+const IMPACT_PER_DAMAGE: f32 = 60.0;
+const SUDDEN_DEATH_DAMAGE_MULTIPLIER: f32 = 2.0;
+/// Damage of whips with a [`HeavyTip`] is multiplied by this.
+const HEAVY_TIP_DAMAGE_MULTIPLIER: f32 = 1.5;
+/// Damage added to every hit of a whip with a [`FireTrail`].
+const FIRE_DAMAGE: f32 = 4.0;
+/// How long the afterimage left where a player was knocked out takes to fade.
+const KNOCK_OUT_FADE: Duration = Duration::from_millis(600);
+/// Hits doing at least this much damage stun.
+const STUN_DAMAGE: f32 = 15.0;
+const STUN: Duration = Duration::from_millis(400);
+/// Grace after a stun wears off, so a player can't be stunned over and over.
+const STUN_GRACE: Duration = Duration::from_millis(500);
+/// Grace after coming back from a knock out.
+const RESPAWN_GRACE: Duration = Duration::from_millis(1500);
+/// Lighter hits slow down for a moment instead, more so when they keep coming.
+const HIT_SLOW: Duration = Duration::from_millis(800);
+const HIT_SLOW_FACTOR: f32 = 0.8;
+/// How long whoever stuns someone can't swing their chain, so they can't keep them stunned.
+const STUN_RECOIL: Duration = Duration::from_millis(300);
+/// Rush after knocking someone out.
+const KNOCK_OUT_BOOST: Duration = Duration::from_secs(2);
+const KNOCK_OUT_BOOST_FACTOR: f32 = 1.3;
+
+#[derive(SubStates, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[source(GameState = GameState::Playing)]
+pub enum MatchPhase {
+    /// Everyone is in place but can't move yet.
+    #[default]
+    Countdown,
+    Fight,
+    /// Time is up, hits do more damage until someone is out.
+    SuddenDeath,
+    /// One player is left standing, or none. Back to the lobby once the timer runs out.
+    Finished,
+}
+
+/// How long the current [`MatchPhase`] has been going, and how long it lasts.
+#[derive(Resource, Clone, Default)]
+pub struct RoundTimer {
+    pub elapsed: Duration,
+    /// `None` for phases that only end when someone wins, like sudden death.
+    limit: Option<Duration>,
+}
+
+impl RoundTimer {
+    fn new(limit: Option<Duration>) -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            limit,
+        }
+    }
+
+    /// Time left, if the phase runs out at all.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.limit.map(|limit| limit.saturating_sub(self.elapsed))
+    }
+
+    /// Advances the timer, true the one time it runs out.
+    fn tick(&mut self, delta: Duration) -> bool {
+        let before = self.elapsed;
+        self.elapsed += delta;
+        self.limit
+            .is_some_and(|limit| before < limit && self.elapsed >= limit)
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.current / self.max
+    }
+}
+
+/// Times a player can still be knocked out before they are out of the match.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Stocks(pub u32);
+
+/// Knock outs a player scored.
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct Score(pub u32);
+
+/// The player who hit this one last, and gets the point if they are knocked out.
+#[derive(Component)]
+pub struct LastHitBy(pub Entity);
+
+/// A player was knocked out and is back at their spawn point.
+#[derive(Event)]
+pub struct Respawned(pub Entity);
+
+/// Damage, knock-outs and respawns, after [`ImpactSystems`].
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RoundSystems;
+
+/// Runs when a stun wears off.
+#[derive(Resource)]
+struct StunRecovery(SystemId<In<Entity>>);
+
+/// Whether players are allowed to move, i.e. the countdown is over and nobody won yet.
+pub fn fighting(phase: Option<Res<State<MatchPhase>>>) -> bool {
+    phase.is_some_and(|phase| matches!(phase.get(), MatchPhase::Fight | MatchPhase::SuddenDeath))
+}
+
+pub struct RoundPlugin;
+
+impl Plugin for RoundPlugin {
+    fn build(&self, app: &mut App) {
+        let stun_recovery = app.register_system(recover_from_stun);
+        app.add_sub_state::<MatchPhase>()
+            .add_event::<Respawned>()
+            .insert_resource(StunRecovery(stun_recovery))
+            .init_resource::<RoundTimer>()
+            .add_systems(OnEnter(MatchPhase::Countdown), start_timer(Some(COUNTDOWN)))
+            .add_systems(OnEnter(MatchPhase::Fight), start_timer(Some(FIGHT)))
+            // Lasts until someone wins.
+            .add_systems(OnEnter(MatchPhase::SuddenDeath), start_timer(None))
+            .add_systems(OnEnter(MatchPhase::Finished), start_timer(Some(FINISHED)))
+            .add_systems(OnExit(GameState::Playing), despawn_players)
+            .add_systems(
+                FixedUpdate,
+                advance_phase
+                    // A match won this tick is finished rather than sudden death.
+                    .before(RoundSystems)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                (damage_players, knock_out, restore_chains, finish_match)
+                    .chain()
+                    .in_set(RoundSystems)
+                    .after(ImpactSystems)
+                    .run_if(fighting),
+            );
+    }
+}
+
+fn start_timer(limit: Option<Duration>) -> impl Fn(ResMut<RoundTimer>) {
+    move |mut timer| *timer = RoundTimer::new(limit)
+}
+
+fn advance_phase(
+    time: Res<Time>,
+    mut timer: ResMut<RoundTimer>,
+    phase: Res<State<MatchPhase>>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !timer.tick(time.delta()) {
+        return;
+    }
+
+    match phase.get() {
+        MatchPhase::Countdown => next_phase.set(MatchPhase::Fight),
+        MatchPhase::Fight => {
+            info!("Time is up, sudden death!");
+            next_phase.set(MatchPhase::SuddenDeath);
+        }
+        MatchPhase::SuddenDeath => {}
+        MatchPhase::Finished => next_state.set(GameState::Lobby),
+    }
+}
+
+fn damage_players(
+    mut commands: Commands,
+    mut impacts: EventReader<ImpactEvent>,
+    phase: Res<State<MatchPhase>>,
+    stun_recovery: Res<StunRecovery>,
+    mut players: Query<&mut Health, (With<Player>, Without<Invulnerable>)>,
+    attackers: Query<(Has<HeavyTip>, Has<FireTrail>)>,
+) {
+    let multiplier = match phase.get() {
+        MatchPhase::SuddenDeath => SUDDEN_DEATH_DAMAGE_MULTIPLIER,
+        _ => 1.0,
+    };
+
+    for impact in impacts.read() {
+        if impact.kind == ImpactKind::Slam {
+            continue;
+        }
+        let Some((target, mut health)) = impact
+            .target
+            .and_then(|target| Some((target, players.get_mut(target).ok()?)))
+        else {
+            continue;
+        };
+        // The tip only weighs or burns anything when it is what hit.
+        let (heavy, burning) = match impact.kind {
+            ImpactKind::Whip => attackers.get(impact.source).unwrap_or_default(),
+            _ => (false, false),
+        };
+        let mut damage = impact.strength / IMPACT_PER_DAMAGE * multiplier;
+        if heavy {
+            damage *= HEAVY_TIP_DAMAGE_MULTIPLIER;
+        }
+        if burning {
+            damage += FIRE_DAMAGE;
+        }
+        health.current = (health.current - damage).max(0.0);
+        commands.entity(target).insert(LastHitBy(impact.source));
+        if damage >= STUN_DAMAGE {
+            commands.entity(target).queue(apply_effect(Stunned(
+                EffectTimer::new(STUN).on_expire(stun_recovery.0),
+            )));
+            if let Ok(mut source) = commands.get_entity(impact.source) {
+                source.queue(apply_effect(ChainLocked(EffectTimer::new(STUN_RECOIL))));
+            }
+        } else {
+            commands.entity(target).queue(apply_effect(Slowed {
+                timer: EffectTimer::new(HIT_SLOW),
+                factor: HIT_SLOW_FACTOR,
+            }));
+        }
+    }
+}
+
+fn recover_from_stun(In(player): In<Entity>, mut commands: Commands) {
+    if let Ok(mut player) = commands.get_entity(player) {
+        player.queue(apply_effect(Invulnerable(EffectTimer::new(STUN_GRACE))));
+    }
+}
+
+/// Players out of health lose a stock and are sent back to their spawn point, good as new and
+/// briefly invulnerable. Whoever hit them last scores. Out of stocks they are out of the match.
+/// Either way a fading afterimage stays behind where they were.
+fn knock_out(
+    mut commands: Commands,
+    mut despawner: Despawner,
+    mut players: Query<
+        (
+            Entity,
+            &mut Health,
+            &mut Stocks,
+            &PlayerNumber,
+            &Chain,
+            &mut Transform,
+            &mut LinearVelocity,
+            &Sprite,
+            Option<&LastHitBy>,
+        ),
+        With<Player>,
+    >,
+    mut links: Query<(&mut Transform, &mut LinearVelocity, &mut AngularVelocity), Without<Player>>,
+    mut scores: Query<&mut Score>,
+    mut respawned: EventWriter<Respawned>,
+) {
+    // Going down in the same tick, they might not be around to get a boost anymore.
+    let knocked_out: Vec<Entity> = players
+        .iter()
+        .filter(|(_, health, ..)| health.current <= 0.0)
+        .map(|(entity, ..)| entity)
+        .collect();
+
+    for (
+        entity,
+        mut health,
+        mut stocks,
+        number,
+        chain,
+        mut transform,
+        mut velocity,
+        sprite,
+        last_hit_by,
+    ) in &mut players
+    {
+        if health.current > 0.0 {
+            continue;
+        }
+        info!("Player {} was knocked out", number.0 + 1);
+
+        commands.spawn((
+            sprite.clone(),
+            *transform,
+            DeleteAt::after(KNOCK_OUT_FADE).fading_out(KNOCK_OUT_FADE),
+            SpawnedInMatch,
+        ));
+
+        if let Some(LastHitBy(attacker)) = last_hit_by {
+            if *attacker != entity {
+                if let Ok(mut score) = scores.get_mut(*attacker) {
+                    score.0 += 1;
+                }
+                if !knocked_out.contains(attacker) {
+                    commands.entity(*attacker).queue(apply_effect(SpeedBoost {
+                        timer: EffectTimer::new(KNOCK_OUT_BOOST),
+                        factor: KNOCK_OUT_BOOST_FACTOR,
+                    }));
+                }
+            }
+            commands.entity(entity).remove::<LastHitBy>();
+        }
+
+        stocks.0 = stocks.0.saturating_sub(1);
+        if stocks.0 == 0 {
+            info!("Player {} is out", number.0 + 1);
+            despawn_player(&mut despawner, entity, chain);
+            continue;
+        }
+
+        let spawn = spawn_point(*number);
+        let offset = spawn - transform.translation.xy();
+        transform.translation = spawn.extend(transform.translation.z);
+        velocity.0 = Vec2::ZERO;
+        // Bring the whole chain along, so its joints don't yank it across the map.
+        for link in &chain.links {
+            if let Ok((mut transform, mut velocity, mut angular_velocity)) = links.get_mut(*link) {
+                transform.translation += offset.extend(0.0);
+                velocity.0 = Vec2::ZERO;
+                angular_velocity.0 = 0.0;
+            }
+        }
+        *health = Health::new(health.max);
+        commands
+            .entity(entity)
+            .remove::<Stunned>()
+            .queue(apply_effect(Invulnerable(EffectTimer::new(RESPAWN_GRACE))));
+        respawned.write(Respawned(entity));
+    }
+}
+
+/// Chains come back as long as they were, whatever was added or cut off.
+fn restore_chains(mut respawned: EventReader<Respawned>, mut chains: ChainEditor) {
+    for Respawned(player) in respawned.read() {
+        chains.set_length(*player, CHAIN_LINK_COUNT);
+    }
+}
+
+/// The match is over when one player is left, or none if they were alone to begin with.
+fn finish_match(
+    lobby: Res<Lobby>,
+    players: Query<(&Stocks, &PlayerNumber), With<Player>>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+) {
+    let mut standing = players.iter().filter(|(stocks, _)| stocks.0 > 0);
+    let winner = standing.next();
+    let more_left = standing.next().is_some();
+
+    match winner {
+        Some((_, number)) if !more_left && lobby.slots.len() > 1 => {
+            info!("Player {} wins!", number.0 + 1);
+            next_phase.set(MatchPhase::Finished);
+        }
+        None => next_phase.set(MatchPhase::Finished),
+        _ => {}
+    }
+}
+
+/// Whoever is left at the end of a match goes back to the lobby with everyone else.
+fn despawn_players(mut despawner: Despawner, players: Query<(Entity, &Chain), With<Player>>) {
+    for (player, chain) in players {
+        despawn_player(&mut despawner, player, chain);
+    }
+}
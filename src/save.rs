@@ -0,0 +1,323 @@
+//! Everything kept between sessions: the [`Settings`] and the player's [`Progress`].
+//!
+//! It is all one RON document, written to `save.ron` in the user's config directory, or to
+//! `localStorage` in the web build. The document carries a version number, so files written by
+//! older builds can be brought up to date when they are read instead of being thrown away.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::Player;
+use crate::determinism::SimulationTick;
+use crate::round::{MatchPhase, Score, Stocks};
+use crate::settings::Settings;
+use crate::tilemap::{CurrentLevel, LEVELS};
+
+/// Version of the save format this build writes. Bump it and add a step to [`migrate`] whenever
+/// something in the file changes in a way `#[serde(default)]` doesn't cover.
+const VERSION: u32 = 1;
+
+/// What the player has achieved so far.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Progress {
+    /// Names from [`LEVELS`] that can be played.
+    pub unlocked_levels: BTreeSet<String>,
+    /// Best results per level name.
+    pub best: BTreeMap<String, LevelStats>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            unlocked_levels: BTreeSet::from([LEVELS[0].to_string()]),
+            best: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct LevelStats {
+    pub matches: u32,
+    /// Shortest time from "Fight!" to a winner, in seconds.
+    pub fastest_win: Option<f32>,
+    /// Most K.O.s a winner scored in one match.
+    pub most_kos: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    settings: Settings,
+    progress: Progress,
+}
+
+/// Just enough of any version of the file to know how to read the rest.
+#[derive(Deserialize)]
+struct Header {
+    /// Missing in files from before there was a version.
+    #[serde(default)]
+    version: u32,
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("No place to keep save data")]
+    NoStorage,
+    #[error("Could not access save file: {0}")]
+    Io(#[from] std::io::Error),
+    #[cfg(target_arch = "wasm32")]
+    #[error("Browser refused to store save data")]
+    Storage,
+    #[error("Malformed save data: {0}")]
+    Parse(#[from] ron::de::SpannedError),
+    #[error("Could not write save data: {0}")]
+    Write(#[from] ron::Error),
+    #[error("Save data is from a newer version ({0})")]
+    TooNew(u32),
+}
+
+/// Reads save data written by this or any older version.
+fn migrate(text: &str) -> Result<SaveFile, SaveError> {
+    let Header { version } = ron::from_str(text)?;
+    match version {
+        // Before there was a version, the file was `settings.ron` and only held the settings.
+        0 => Ok(SaveFile {
+            version: VERSION,
+            settings: ron::from_str(text)?,
+            progress: Progress::default(),
+        }),
+        VERSION => Ok(ron::from_str(text)?),
+        newer => Err(SaveError::TooNew(newer)),
+    }
+}
+
+fn load() -> Result<Option<SaveFile>, SaveError> {
+    storage::read()?.map(|text| migrate(&text)).transpose()
+}
+
+fn save(settings: &Settings, progress: &Progress) -> Result<(), SaveError> {
+    storage::write(&to_ron(settings, progress)?)
+}
+
+fn to_ron(settings: &Settings, progress: &Progress) -> Result<String, SaveError> {
+    let file = SaveFile {
+        version: VERSION,
+        settings: settings.clone(),
+        progress: progress.clone(),
+    };
+    Ok(ron::ser::to_string_pretty(
+        &file,
+        ron::ser::PrettyConfig::default(),
+    )?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use std::path::PathBuf;
+
+    use super::SaveError;
+
+    const FILE: &str = "save.ron";
+    /// Where older versions kept just the settings.
+    const LEGACY_FILE: &str = "settings.ron";
+
+    /// Where save data is kept, following the platform's conventions.
+    fn dir() -> Option<PathBuf> {
+        let config = if cfg!(windows) {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join("Library/Application Support"))
+        } else {
+            std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+                })
+        };
+        config.map(|config| config.join("chainwhips"))
+    }
+
+    pub fn read() -> Result<Option<String>, SaveError> {
+        let dir = dir().ok_or(SaveError::NoStorage)?;
+        for file in [FILE, LEGACY_FILE] {
+            match std::fs::read_to_string(dir.join(file)) {
+                Ok(text) => return Ok(Some(text)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn write(text: &str) -> Result<(), SaveError> {
+        let dir = dir().ok_or(SaveError::NoStorage)?;
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(FILE), text)?;
+        Ok(())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    use super::SaveError;
+
+    const KEY: &str = "chainwhips.save";
+
+    fn local_storage() -> Result<web_sys::Storage, SaveError> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or(SaveError::NoStorage)
+    }
+
+    pub fn read() -> Result<Option<String>, SaveError> {
+        local_storage()?
+            .get_item(KEY)
+            .map_err(|_| SaveError::Storage)
+    }
+
+    pub fn write(text: &str) -> Result<(), SaveError> {
+        local_storage()?
+            .set_item(KEY, text)
+            .map_err(|_| SaveError::Storage)
+    }
+}
+
+/// Ticks "Fight!" was shown and the match was over at, to know how long it took. Rollbacks set
+/// them again, so the match is only recorded once it is really over.
+#[derive(Resource, Default)]
+struct MatchTicks {
+    fight: u32,
+    finished: u32,
+}
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let (settings, progress) = match load() {
+            Ok(Some(file)) => (file.settings, file.progress),
+            Ok(None) => default(),
+            Err(e) => {
+                warn!("Starting without save data: {e}");
+                default()
+            }
+        };
+
+        app.insert_resource(settings.bindings.clone())
+            .insert_resource(settings)
+            .insert_resource(progress)
+            .init_resource::<MatchTicks>()
+            .add_systems(OnEnter(MatchPhase::Fight), start_match)
+            .add_systems(OnEnter(MatchPhase::Finished), finish_match)
+            .add_systems(OnExit(MatchPhase::Finished), record_match)
+            .add_systems(
+                Update,
+                write_save.run_if(resource_changed::<Settings>.or(resource_changed::<Progress>)),
+            );
+    }
+}
+
+fn start_match(tick: Res<SimulationTick>, mut ticks: ResMut<MatchTicks>) {
+    ticks.fight = tick.0;
+}
+
+fn finish_match(tick: Res<SimulationTick>, mut ticks: ResMut<MatchTicks>) {
+    ticks.finished = tick.0;
+}
+
+/// Counts the match toward the level's stats, and unlocks the next level if someone won.
+fn record_match(
+    time: Res<Time<Fixed>>,
+    ticks: Res<MatchTicks>,
+    level: Res<CurrentLevel>,
+    mut progress: ResMut<Progress>,
+    players: Query<(&Stocks, &Score), With<Player>>,
+) {
+    let stats = progress.best.entry(level.name().to_string()).or_default();
+    stats.matches += 1;
+
+    let Some((_, score)) = players.iter().find(|(stocks, _)| stocks.0 > 0) else {
+        return;
+    };
+    let duration = (ticks.finished - ticks.fight) as f32 * time.timestep().as_secs_f32();
+    stats.fastest_win = Some(
+        stats
+            .fastest_win
+            .map_or(duration, |best| best.min(duration)),
+    );
+    stats.most_kos = stats.most_kos.max(score.0);
+
+    if let Some(next) = LEVELS.get(level.0 + 1) {
+        progress.unlocked_levels.insert(next.to_string());
+    }
+}
+
+fn write_save(settings: Res<Settings>, progress: Res<Progress>) {
+    // Both are only added when the game starts, nothing to save yet.
+    if settings.is_added() && progress.is_added() {
+        return;
+    }
+    if let Err(e) = save(&settings, &progress) {
+        error!("Could not save: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_settings_from_before_versions() {
+        let file = migrate("(master_volume: 0.5, music_volume: 0.25, fullscreen: true)").unwrap();
+
+        assert_eq!(file.version, VERSION);
+        assert_eq!(
+            file.settings,
+            Settings {
+                master_volume: 0.5,
+                music_volume: 0.25,
+                fullscreen: true,
+                ..default()
+            }
+        );
+        assert_eq!(file.progress, Progress::default());
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let settings = Settings {
+            screen_shake: 0.0,
+            resolution: 2,
+            ..default()
+        };
+        let mut progress = Progress::default();
+        progress.unlocked_levels.insert("Another Map".to_string());
+        progress.best.insert(
+            LEVELS[0].to_string(),
+            LevelStats {
+                matches: 3,
+                fastest_win: Some(42.5),
+                most_kos: 2,
+            },
+        );
+
+        let file = migrate(&to_ron(&settings, &progress).unwrap()).unwrap();
+
+        assert_eq!(file.version, VERSION);
+        assert_eq!(file.settings, settings);
+        assert_eq!(file.progress, progress);
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let text = format!("(version: {}, settings: (), progress: ())", VERSION + 1);
+
+        assert!(matches!(migrate(&text), Err(SaveError::TooNew(v)) if v == VERSION + 1));
+    }
+}
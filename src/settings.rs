@@ -0,0 +1,80 @@
+//! Options the player can change in the settings menu.
+//!
+//! [`Settings`] is the single source of truth: whenever it changes, it is pushed to the resources
+//! the rest of the game reads ([`AudioVolumes`], [`CameraEffectsSettings`], [`KeyBindings`] and
+//! the window). Keeping it between sessions is up to [`crate::save`].
+
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowMode};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::AudioVolumes;
+use crate::camera::effects::CameraEffectsSettings;
+use crate::input::KeyBindings;
+
+/// Window sizes offered in the settings menu, in logical pixels.
+pub const RESOLUTIONS: [(u32, u32); 4] = [(1280, 720), (1600, 900), (1920, 1080), (2560, 1440)];
+
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// Linear, 0 to 1, see [`AudioVolumes`].
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    /// Scales screen shake, 0 turns it off.
+    pub screen_shake: f32,
+    pub fullscreen: bool,
+    /// Index into [`RESOLUTIONS`], used while windowed.
+    pub resolution: usize,
+    pub bindings: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let volumes = AudioVolumes::default();
+        Self {
+            master_volume: volumes.master,
+            music_volume: volumes.music,
+            sfx_volume: volumes.sfx,
+            screen_shake: 1.0,
+            fullscreen: false,
+            resolution: 0,
+            bindings: KeyBindings::default(),
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, apply_settings.run_if(resource_changed::<Settings>));
+    }
+}
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut volumes: ResMut<AudioVolumes>,
+    mut effects: ResMut<CameraEffectsSettings>,
+    mut bindings: ResMut<KeyBindings>,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+) {
+    volumes.master = settings.master_volume;
+    volumes.music = settings.music_volume;
+    volumes.sfx = settings.sfx_volume;
+    effects.shake_intensity = settings.screen_shake;
+    *bindings = settings.bindings.clone();
+
+    window.mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+    } else {
+        WindowMode::Windowed
+    };
+    // On the web the canvas decides the size.
+    if cfg!(not(target_arch = "wasm32"))
+        && let Some((width, height)) = RESOLUTIONS.get(settings.resolution)
+    {
+        window.resolution.set(*width as f32, *height as f32);
+    }
+}
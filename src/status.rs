@@ -0,0 +1,533 @@
+//! Timed effects on players, like being stunned or sped up.
+//!
+//! Every effect is its own component holding an [`EffectTimer`], so gameplay can just ask for
+//! `Has<Stunned>`. They are all counted down by [`tick_effects`], each fixed tick, and removed once
+//! their time is up, after which their `on_expire` system runs. Add them with [`apply_effect`],
+//! which stacks them with what the player already has according to [`TimedEffect::STACKING`].
+//! While an effect lasts an icon for it floats above the player.
+
+use std::time::Duration;
+
+use bevy::ecs::component::Mutable;
+use bevy::ecs::query::QueryItem;
+use bevy::ecs::system::SystemId;
+use bevy::prelude::*;
+use bevy_tnua::prelude::TnuaUserControlsSystemSet;
+
+use crate::{GameState, Player};
+
+/// Height of the row of icons above the middle of the player.
+const ICON_HEIGHT: f32 = 48.0;
+const ICON_SPACING: f32 = 16.0;
+
+/// What happens when an effect is applied to a player who already has it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stacking {
+    /// Lasts for whichever is longer, what is left or the new duration.
+    Refresh,
+    /// The new duration is added to what is left.
+    Extend,
+    /// Gets stronger with every application, up to `max` stacks, and lasts for the longer.
+    Stack { max: u32 },
+}
+
+/// How long an effect has left, and what to do when it is over.
+#[derive(Clone, Debug)]
+pub struct EffectTimer {
+    remaining: Duration,
+    stacks: u32,
+    /// Gets the entity the effect was on.
+    on_expire: Option<SystemId<In<Entity>>>,
+}
+
+impl EffectTimer {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            remaining: duration,
+            stacks: 1,
+            on_expire: None,
+        }
+    }
+
+    pub fn on_expire(mut self, system: SystemId<In<Entity>>) -> Self {
+        self.on_expire = Some(system);
+        self
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+}
+
+/// Everything the icons above players are drawn for, in the order they are shown.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StatusKind {
+    Stunned,
+    Invulnerable,
+    SpeedBoost,
+    Slowed,
+    ChainLocked,
+    HeavyTip,
+    FireTrail,
+    DoubleJump,
+}
+
+impl StatusKind {
+    pub const ALL: [StatusKind; 8] = [
+        StatusKind::Stunned,
+        StatusKind::Invulnerable,
+        StatusKind::SpeedBoost,
+        StatusKind::Slowed,
+        StatusKind::ChainLocked,
+        StatusKind::HeavyTip,
+        StatusKind::FireTrail,
+        StatusKind::DoubleJump,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatusKind::Stunned => "Stunned",
+            StatusKind::Invulnerable => "Shield",
+            StatusKind::SpeedBoost => "Speed",
+            StatusKind::Slowed => "Slowed",
+            StatusKind::ChainLocked => "Chain locked",
+            StatusKind::HeavyTip => "Heavy tip",
+            StatusKind::FireTrail => "Fire",
+            StatusKind::DoubleJump => "Double jump",
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            StatusKind::Stunned => "*",
+            StatusKind::Invulnerable => "O",
+            StatusKind::SpeedBoost => ">>",
+            StatusKind::Slowed => "<<",
+            StatusKind::ChainLocked => "#",
+            StatusKind::HeavyTip => "@",
+            StatusKind::FireTrail => "~",
+            StatusKind::DoubleJump => "^^",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            StatusKind::Stunned => Color::srgb(1.0, 0.9, 0.2),
+            StatusKind::Invulnerable => Color::srgb(0.6, 0.9, 1.0),
+            StatusKind::SpeedBoost => Color::srgb(0.3, 1.0, 0.4),
+            StatusKind::Slowed => Color::srgb(0.5, 0.5, 1.0),
+            StatusKind::ChainLocked => Color::srgb(1.0, 0.4, 0.3),
+            StatusKind::HeavyTip => Color::srgb(0.7, 0.7, 0.75),
+            StatusKind::FireTrail => Color::srgb(1.0, 0.55, 0.1),
+            StatusKind::DoubleJump => Color::srgb(0.9, 0.6, 1.0),
+        }
+    }
+}
+
+pub trait TimedEffect: Component<Mutability = Mutable> + Clone {
+    const KIND: StatusKind;
+    const STACKING: Stacking;
+
+    fn timer(&self) -> &EffectTimer;
+    fn timer_mut(&mut self) -> &mut EffectTimer;
+
+    /// Combines anything besides the timer when `new` stacks onto `self`.
+    fn merge(&mut self, _new: &Self) {}
+}
+
+/// Can't move, jump, slam or swing the chain.
+#[derive(Component, Clone, Debug)]
+pub struct Stunned(pub EffectTimer);
+
+/// Takes no damage.
+#[derive(Component, Clone, Debug)]
+pub struct Invulnerable(pub EffectTimer);
+
+/// Walks `factor` times as fast.
+#[derive(Component, Clone, Debug)]
+pub struct SpeedBoost {
+    pub timer: EffectTimer,
+    pub factor: f32,
+}
+
+/// Walks `factor` times as fast for every stack, so slower the more often it is applied.
+#[derive(Component, Clone, Debug)]
+pub struct Slowed {
+    pub timer: EffectTimer,
+    pub factor: f32,
+}
+
+/// Can't swing the chain.
+#[derive(Component, Clone, Debug)]
+pub struct ChainLocked(pub EffectTimer);
+
+impl TimedEffect for Stunned {
+    const KIND: StatusKind = StatusKind::Stunned;
+    const STACKING: Stacking = Stacking::Refresh;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.0
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.0
+    }
+}
+
+impl TimedEffect for Invulnerable {
+    const KIND: StatusKind = StatusKind::Invulnerable;
+    const STACKING: Stacking = Stacking::Refresh;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.0
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.0
+    }
+}
+
+impl TimedEffect for SpeedBoost {
+    const KIND: StatusKind = StatusKind::SpeedBoost;
+    const STACKING: Stacking = Stacking::Extend;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.timer
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.timer
+    }
+
+    fn merge(&mut self, new: &Self) {
+        self.factor = self.factor.max(new.factor);
+    }
+}
+
+impl TimedEffect for Slowed {
+    const KIND: StatusKind = StatusKind::Slowed;
+    const STACKING: Stacking = Stacking::Stack { max: 3 };
+
+    fn timer(&self) -> &EffectTimer {
+        &self.timer
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.timer
+    }
+}
+
+impl TimedEffect for ChainLocked {
+    const KIND: StatusKind = StatusKind::ChainLocked;
+    const STACKING: Stacking = Stacking::Refresh;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.0
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.0
+    }
+}
+
+/// The last link of the chain is heavier, and its hits do more damage.
+#[derive(Component, Clone, Debug)]
+pub struct HeavyTip(pub EffectTimer);
+
+/// The chain burns, trailing fire and doing extra damage.
+#[derive(Component, Clone, Debug)]
+pub struct FireTrail(pub EffectTimer);
+
+/// Can jump once more in the air.
+#[derive(Component, Clone, Debug)]
+pub struct DoubleJump {
+    pub timer: EffectTimer,
+    /// Already jumped in the air since last on the ground.
+    pub used: bool,
+    /// Jump was held last tick, so holding it through a jump doesn't jump again.
+    pub held: bool,
+}
+
+impl DoubleJump {
+    pub fn new(timer: EffectTimer) -> Self {
+        Self {
+            timer,
+            used: false,
+            held: false,
+        }
+    }
+}
+
+impl TimedEffect for HeavyTip {
+    const KIND: StatusKind = StatusKind::HeavyTip;
+    const STACKING: Stacking = Stacking::Refresh;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.0
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.0
+    }
+}
+
+impl TimedEffect for FireTrail {
+    const KIND: StatusKind = StatusKind::FireTrail;
+    const STACKING: Stacking = Stacking::Extend;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.0
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.0
+    }
+}
+
+impl TimedEffect for DoubleJump {
+    const KIND: StatusKind = StatusKind::DoubleJump;
+    const STACKING: Stacking = Stacking::Refresh;
+
+    fn timer(&self) -> &EffectTimer {
+        &self.timer
+    }
+
+    fn timer_mut(&mut self) -> &mut EffectTimer {
+        &mut self.timer
+    }
+}
+
+/// How fast a player walks with whatever boosts and slows they have, 1 being normal.
+pub fn speed_factor(boost: Option<&SpeedBoost>, slowed: Option<&Slowed>) -> f32 {
+    boost.map_or(1.0, |boost| boost.factor)
+        * slowed.map_or(1.0, |slowed| slowed.factor.powi(slowed.timer.stacks as i32))
+}
+
+/// Adds `effect` to the entity, or stacks it onto the one already there. For
+/// [`EntityCommands::queue`].
+pub fn apply_effect<T: TimedEffect>(effect: T) -> impl EntityCommand {
+    move |mut entity: EntityWorldMut| {
+        let Some(mut existing) = entity.get_mut::<T>() else {
+            entity.insert(effect);
+            return;
+        };
+        existing.merge(&effect);
+        let new = effect.timer();
+        let timer = existing.timer_mut();
+        match T::STACKING {
+            Stacking::Refresh => timer.remaining = timer.remaining.max(new.remaining),
+            Stacking::Extend => timer.remaining += new.remaining,
+            Stacking::Stack { max } => {
+                timer.stacks = (timer.stacks + 1).min(max);
+                timer.remaining = timer.remaining.max(new.remaining);
+            }
+        }
+        if new.on_expire.is_some() {
+            timer.on_expire = new.on_expire;
+        }
+    }
+}
+
+/// Every effect on a player, so [`crate::net`] can roll them back and the HUD can list them.
+#[derive(Clone, Default)]
+pub struct ActiveEffects {
+    stunned: Option<Stunned>,
+    invulnerable: Option<Invulnerable>,
+    speed_boost: Option<SpeedBoost>,
+    slowed: Option<Slowed>,
+    chain_locked: Option<ChainLocked>,
+    heavy_tip: Option<HeavyTip>,
+    fire_trail: Option<FireTrail>,
+    double_jump: Option<DoubleJump>,
+}
+
+/// What [`ActiveEffects::new`] is made from.
+pub type EffectsQuery = (
+    Option<&'static Stunned>,
+    Option<&'static Invulnerable>,
+    Option<&'static SpeedBoost>,
+    Option<&'static Slowed>,
+    Option<&'static ChainLocked>,
+    Option<&'static HeavyTip>,
+    Option<&'static FireTrail>,
+    Option<&'static DoubleJump>,
+);
+
+impl ActiveEffects {
+    pub fn new(
+        (
+            stunned,
+            invulnerable,
+            speed_boost,
+            slowed,
+            chain_locked,
+            heavy_tip,
+            fire_trail,
+            double_jump,
+        ): QueryItem<EffectsQuery>,
+    ) -> Self {
+        Self {
+            stunned: stunned.cloned(),
+            invulnerable: invulnerable.cloned(),
+            speed_boost: speed_boost.cloned(),
+            slowed: slowed.cloned(),
+            chain_locked: chain_locked.cloned(),
+            heavy_tip: heavy_tip.cloned(),
+            fire_trail: fire_trail.cloned(),
+            double_jump: double_jump.cloned(),
+        }
+    }
+
+    /// The kind of every effect, with its timer, in the order of [`StatusKind::ALL`].
+    pub fn iter(&self) -> impl Iterator<Item = (StatusKind, &EffectTimer)> {
+        [
+            entry(&self.stunned),
+            entry(&self.invulnerable),
+            entry(&self.speed_boost),
+            entry(&self.slowed),
+            entry(&self.chain_locked),
+            entry(&self.heavy_tip),
+            entry(&self.fire_trail),
+            entry(&self.double_jump),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.iter().any(|(active, _)| active == kind)
+    }
+
+    pub fn restore(&self, entity: &mut EntityWorldMut) {
+        restore(entity, &self.stunned);
+        restore(entity, &self.invulnerable);
+        restore(entity, &self.speed_boost);
+        restore(entity, &self.slowed);
+        restore(entity, &self.chain_locked);
+        restore(entity, &self.heavy_tip);
+        restore(entity, &self.fire_trail);
+        restore(entity, &self.double_jump);
+    }
+}
+
+fn entry<T: TimedEffect>(effect: &Option<T>) -> Option<(StatusKind, &EffectTimer)> {
+    effect.as_ref().map(|effect| (T::KIND, effect.timer()))
+}
+
+fn restore<T: TimedEffect>(entity: &mut EntityWorldMut, effect: &Option<T>) {
+    match effect {
+        Some(effect) => {
+            entity.insert(effect.clone());
+        }
+        None => {
+            entity.remove::<T>();
+        }
+    }
+}
+
+/// One of the icons above a player.
+#[derive(Component)]
+struct StatusIcon(StatusKind);
+
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            tick_effects
+                .before(TnuaUserControlsSystemSet)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (spawn_icons, show_icons)
+                .chain()
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+/// Counts every effect down, in the fixed clock so they last the same ticks on every run.
+pub fn tick_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    stunned: Query<(Entity, &mut Stunned)>,
+    invulnerable: Query<(Entity, &mut Invulnerable)>,
+    speed_boost: Query<(Entity, &mut SpeedBoost)>,
+    slowed: Query<(Entity, &mut Slowed)>,
+    chain_locked: Query<(Entity, &mut ChainLocked)>,
+    heavy_tip: Query<(Entity, &mut HeavyTip)>,
+    fire_trail: Query<(Entity, &mut FireTrail)>,
+    double_jump: Query<(Entity, &mut DoubleJump)>,
+) {
+    let delta = time.delta();
+    count_down(&mut commands, delta, stunned);
+    count_down(&mut commands, delta, invulnerable);
+    count_down(&mut commands, delta, speed_boost);
+    count_down(&mut commands, delta, slowed);
+    count_down(&mut commands, delta, chain_locked);
+    count_down(&mut commands, delta, heavy_tip);
+    count_down(&mut commands, delta, fire_trail);
+    count_down(&mut commands, delta, double_jump);
+}
+
+fn count_down<T: TimedEffect>(
+    commands: &mut Commands,
+    delta: Duration,
+    effects: Query<(Entity, &mut T)>,
+) {
+    for (entity, mut effect) in effects {
+        let timer = effect.timer_mut();
+        timer.remaining = timer.remaining.saturating_sub(delta);
+        if !timer.remaining.is_zero() {
+            continue;
+        }
+
+        let on_expire = timer.on_expire;
+        commands.entity(entity).remove::<T>();
+        if let Some(system) = on_expire {
+            commands.run_system_with(system, entity);
+        }
+    }
+}
+
+fn spawn_icons(mut commands: Commands, players: Query<Entity, Added<Player>>) {
+    for player in players {
+        commands.entity(player).with_children(|parent| {
+            for kind in StatusKind::ALL {
+                parent.spawn((
+                    StatusIcon(kind),
+                    Text2d::new(kind.icon()),
+                    TextFont::from_font_size(16.0),
+                    TextColor(kind.color()),
+                    Transform::from_xyz(0.0, ICON_HEIGHT, 1.0),
+                    Visibility::Hidden,
+                ));
+            }
+        });
+    }
+}
+
+/// Shows the icons of every effect a player has, side by side and centered over them.
+fn show_icons(
+    players: Query<(&Children, EffectsQuery), With<Player>>,
+    mut icons: Query<(&StatusIcon, &mut Transform, &mut Visibility)>,
+) {
+    for (children, effects) in players {
+        let effects = ActiveEffects::new(effects);
+        let shown = effects.iter().count();
+
+        let mut slot = 0;
+        let mut row = icons.iter_many_mut(children);
+        while let Some((StatusIcon(kind), mut transform, mut visibility)) = row.fetch_next() {
+            if !effects.has(*kind) {
+                *visibility = Visibility::Hidden;
+                continue;
+            }
+            transform.translation.x = (slot as f32 - (shown as f32 - 1.0) / 2.0) * ICON_SPACING;
+            *visibility = Visibility::Inherited;
+            slot += 1;
+        }
+    }
+}
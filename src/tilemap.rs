@@ -3,8 +3,59 @@ use bevy_ecs_tilemap::prelude::*;
 
 pub mod helpers;
 
-pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let map_handle = helpers::tiled::TiledMapHandle(asset_server.load("The Map.tmx"));
+/// Every level, in the order they are unlocked. Each is a `.tmx` file in `assets`.
+pub const LEVELS: [&str; 1] = ["The Map"];
+
+/// Index into [`LEVELS`] of the level being played.
+#[derive(Resource, Clone, Copy, Default, Debug)]
+pub struct CurrentLevel(pub usize);
+
+impl CurrentLevel {
+    pub fn name(&self) -> &'static str {
+        LEVELS[self.0]
+    }
+}
+
+/// The world space area covered by the loaded map. Tiled maps are centered on the origin.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct MapBounds(pub Rect);
+
+impl MapBounds {
+    pub fn from_map(map: &tiled::Map) -> Self {
+        let size = Vec2::new(
+            (map.width * map.tile_width) as f32,
+            (map.height * map.tile_height) as f32,
+        );
+        MapBounds(Rect::from_center_size(Vec2::ZERO, size))
+    }
+}
+
+/// Every object of class `class` on the object layers of `map`, with where it is in the world.
+pub fn objects_of_class<'map>(
+    map: &'map tiled::Map,
+    class: &'map str,
+) -> impl Iterator<Item = (tiled::Object<'map>, Vec2)> + 'map {
+    // Tiled counts pixels down from the top left corner, our maps are centered.
+    let size = Vec2::new(
+        (map.width * map.tile_width) as f32,
+        (map.height * map.tile_height) as f32,
+    );
+    map.layers()
+        .filter_map(|layer| match layer.layer_type() {
+            tiled::LayerType::Objects(objects) => Some(objects),
+            _ => None,
+        })
+        .flat_map(|objects| objects.objects())
+        .filter(move |object| object.user_type == class)
+        .map(move |object| {
+            let position = Vec2::new(object.x - size.x / 2.0, size.y / 2.0 - object.y);
+            (object, position)
+        })
+}
+
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>, level: Res<CurrentLevel>) {
+    let map_handle =
+        helpers::tiled::TiledMapHandle(asset_server.load(format!("{}.tmx", level.name())));
 
     commands.spawn(helpers::tiled::TiledMapBundle {
         tiled_map: map_handle,
@@ -32,6 +32,9 @@ use bevy::{
 use bevy_ecs_tilemap::prelude::*;
 use thiserror::Error;
 
+use crate::grapple::Grappleable;
+use crate::tilemap::MapBounds;
+
 #[derive(Default)]
 pub struct TiledMapPlugin;
 
@@ -248,6 +251,8 @@ pub fn process_loaded_maps(
                 continue;
             }
             if let Some(tiled_map) = maps.get(&map_handle.0) {
+                commands.insert_resource(MapBounds::from_map(&tiled_map.map));
+
                 // TODO: Create a RemoveMap component..
                 for layer_entity in layer_storage.storage.values() {
                     if let Ok((_, layer_tile_storage)) = tile_storage_query.get(*layer_entity) {
@@ -368,6 +373,13 @@ pub fn process_loaded_maps(
                                     _ => unreachable!()
                                 };
 
+                                let grappleable = layer_tile.get_tile().is_some_and(|tile| {
+                                    matches!(
+                                        tile.properties.get("grapple"),
+                                        Some(tiled::PropertyValue::BoolValue(true))
+                                    )
+                                });
+
                                 let tile_pos = TilePos { x, y };
                                 let tile_entity = commands
                                     .spawn((
@@ -396,6 +408,9 @@ pub fn process_loaded_maps(
                                         //},
                                     ))
                                     .id();
+                                if grappleable {
+                                    commands.entity(tile_entity).insert(Grappleable);
+                                }
                                 tile_storage.set(&tile_pos, tile_entity);
                             }
                         }
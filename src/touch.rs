@@ -0,0 +1,188 @@
+//! On-screen touch controls, for playing the web build on phones.
+//!
+//! Touches are sorted into regions by where they started, so a thumb can slide off a button
+//! without letting go of it. The result is merged into the keyboard player's
+//! [`InputFrame`](crate::input::InputFrame) by [`gather_input`](crate::input::gather_input).
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+/// How far, in logical pixels, a thumb has to be dragged to fully tilt the stick or swing the
+/// chain.
+const DRAG_DISTANCE: f32 = 60.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TouchAction {
+    Stick,
+    ChainAim,
+    Grapple,
+    Slam,
+    Jump,
+}
+
+/// Where each action lives, as fractions of the window with the origin in the top left.
+const REGIONS: [(TouchAction, Rect); 5] = [
+    (
+        TouchAction::Stick,
+        Rect {
+            min: Vec2::new(0.0, 0.5),
+            max: Vec2::new(0.35, 1.0),
+        },
+    ),
+    (
+        TouchAction::ChainAim,
+        Rect {
+            min: Vec2::new(0.35, 0.6),
+            max: Vec2::new(0.65, 1.0),
+        },
+    ),
+    (
+        TouchAction::Grapple,
+        Rect {
+            min: Vec2::new(0.65, 0.5),
+            max: Vec2::new(0.8, 0.75),
+        },
+    ),
+    (
+        TouchAction::Slam,
+        Rect {
+            min: Vec2::new(0.65, 0.75),
+            max: Vec2::new(0.8, 1.0),
+        },
+    ),
+    (
+        TouchAction::Jump,
+        Rect {
+            min: Vec2::new(0.8, 0.7),
+            max: Vec2::new(1.0, 1.0),
+        },
+    ),
+];
+
+/// What the touch screen is asking for right now.
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    /// Set once we have seen a touch. The overlay is hidden until then.
+    pub enabled: bool,
+    /// -1 to 1.
+    pub walk: f32,
+    /// -1 to 1.
+    pub chain: f32,
+    pub jump: bool,
+    pub jump_just_pressed: bool,
+    pub slam: bool,
+    pub grapple: bool,
+}
+
+#[derive(Component)]
+struct TouchOverlay;
+
+#[derive(Component)]
+struct TouchRegion(TouchAction);
+
+pub struct TouchControlsPlugin;
+
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>()
+            .add_systems(Startup, spawn_overlay)
+            .add_systems(Update, (read_touches, update_overlay).chain());
+    }
+}
+
+fn spawn_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            TouchOverlay,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            Visibility::Hidden,
+            // Above the lobby and everything else.
+            GlobalZIndex(10),
+        ))
+        .with_children(|parent| {
+            for (action, rect) in REGIONS {
+                let label = match action {
+                    TouchAction::Stick => "< move >",
+                    TouchAction::ChainAim => "< whip >",
+                    TouchAction::Grapple => "grapple",
+                    TouchAction::Slam => "slam",
+                    TouchAction::Jump => "jump",
+                };
+                parent
+                    .spawn((
+                        TouchRegion(action),
+                        Node {
+                            position_type: PositionType::Absolute,
+                            left: Val::Percent(rect.min.x * 100.0),
+                            top: Val::Percent(rect.min.y * 100.0),
+                            width: Val::Percent(rect.width() * 100.0),
+                            height: Val::Percent(rect.height() * 100.0),
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            border: UiRect::all(Val::Px(2.0)),
+                            ..default()
+                        },
+                        BorderColor(Color::WHITE.with_alpha(0.3)),
+                        BackgroundColor(Color::WHITE.with_alpha(0.05)),
+                    ))
+                    .with_child(Text::new(label));
+            }
+        });
+}
+
+fn read_touches(
+    touches: Res<Touches>,
+    window: Single<&Window, With<PrimaryWindow>>,
+    mut controls: ResMut<TouchControls>,
+) {
+    let enabled = controls.enabled || touches.iter_just_pressed().next().is_some();
+    let jump_was_pressed = controls.jump;
+    *controls = TouchControls {
+        enabled,
+        ..default()
+    };
+
+    let size = window.size();
+    for touch in touches.iter() {
+        let start = touch.start_position() / size;
+        let Some((action, _)) = REGIONS.iter().find(|(_, rect)| rect.contains(start)) else {
+            continue;
+        };
+        let drag =
+            ((touch.position().x - touch.start_position().x) / DRAG_DISTANCE).clamp(-1.0, 1.0);
+
+        match action {
+            TouchAction::Stick => controls.walk = drag,
+            TouchAction::ChainAim => controls.chain = drag,
+            TouchAction::Grapple => controls.grapple = true,
+            TouchAction::Slam => controls.slam = true,
+            TouchAction::Jump => controls.jump = true,
+        }
+    }
+    controls.jump_just_pressed = controls.jump && !jump_was_pressed;
+}
+
+fn update_overlay(
+    controls: Res<TouchControls>,
+    mut overlay: Single<&mut Visibility, With<TouchOverlay>>,
+    regions: Query<(&TouchRegion, &mut BackgroundColor)>,
+) {
+    if controls.enabled {
+        **overlay = Visibility::Inherited;
+    }
+
+    for (TouchRegion(action), mut background) in regions {
+        let active = match action {
+            TouchAction::Stick => controls.walk != 0.0,
+            TouchAction::ChainAim => controls.chain != 0.0,
+            TouchAction::Grapple => controls.grapple,
+            TouchAction::Slam => controls.slam,
+            TouchAction::Jump => controls.jump,
+        };
+        background.0 = Color::WHITE.with_alpha(if active { 0.2 } else { 0.05 });
+    }
+}